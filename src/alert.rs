@@ -0,0 +1,101 @@
+use std::io;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use regex::Regex;
+use serde::de;
+use serde::{Deserialize, Deserializer};
+
+use crate::config;
+use crate::filter;
+use crate::format::Log;
+
+/// A rule that draws attention to new logs that match it. Logs match if they have one of the
+/// levels and match the regex; rules without either match every log.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Alert {
+    /// Levels of the logs to match, written like in filters: `WARN+` or `INFO,ERROR`.
+    #[serde(default, deserialize_with = "deserialize_levels")]
+    pub level: Option<Vec<&'static str>>,
+    #[serde(default, deserialize_with = "deserialize_some_regex")]
+    pub regex: Option<Regex>,
+    /// Ring the terminal bell.
+    #[serde(default)]
+    pub bell: bool,
+    /// Show the log in the status bar for a few seconds.
+    #[serde(default)]
+    pub flash: bool,
+    /// Shell command to run, with the log in the environment variables `LOG_LINE`, `LOG_TIME`,
+    /// `LOG_THREAD`, `LOG_LEVEL`, `LOG_LOGGER` and `LOG_MESSAGE`.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Minimum number of seconds between two alerts of this rule, so that floods of logs do not
+    /// trigger a flood of alerts.
+    #[serde(default)]
+    pub cooldown: u64,
+}
+
+impl Alert {
+    /// Check whether the log `line`, split into `log` if it is in a known format, matches.
+    pub fn matches(&self, line: &str, log: Option<&Log>) -> bool {
+        if let Some(levels) = &self.level {
+            match log {
+                Some(log) if filter::has_level(levels, log.level) => (),
+                _ => return false,
+            }
+        }
+        match &self.regex {
+            Some(regex) => regex.is_match(line),
+            None => true,
+        }
+    }
+
+    /// Start the command of the rule, if any, without waiting for it to finish.
+    pub fn run_command(&self, line: &str, log: Option<&Log>) -> io::Result<()> {
+        let command = match &self.command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let (time, thread, level, logger, message) = match log {
+            Some(l) => (l.time, l.thread, l.level, l.logger, l.message),
+            None => ("", "", "", "", line),
+        };
+        // The output would mess up the screen, so discard it.
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("LOG_LINE", line)
+            .env("LOG_TIME", time)
+            .env("LOG_THREAD", thread)
+            .env("LOG_LEVEL", level)
+            .env("LOG_LOGGER", logger)
+            .env("LOG_MESSAGE", message)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+fn deserialize_levels<'de, D: Deserializer<'de>>(
+    de: D,
+) -> Result<Option<Vec<&'static str>>, D::Error> {
+    let value = String::deserialize(de)?;
+    filter::parse_levels(&value)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+fn deserialize_some_regex<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Regex>, D::Error> {
+    config::deserialize_regex(de).map(Some)
+}
+
+/// Ring the terminal bell.
+pub fn ring_bell() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+}
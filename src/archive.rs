@@ -0,0 +1,381 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, Local, NaiveDate};
+use regex::Regex;
+use serde::de;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::config;
+
+/// Built-in naming schemes of archived logs, which can be used in the configuration by name.
+const PROFILES: &[(&str, &str, SortKey)] = &[
+    // Vanilla, Fabric, Paper, Spigot, Velocity and Forge's latest.log:
+    // 2024-01-01-1.log.gz
+    (
+        "vanilla",
+        r"^(?P<date>\d{4}-\d\d-\d\d)-(?P<index>\d+)\.log(?:\.\w+)?$",
+        SortKey::Date,
+    ),
+    // Forge's debug.log:
+    // debug-1.log.gz
+    (
+        "forge-debug",
+        r"^debug-(?P<index>\d+)\.log(?:\.\w+)?$",
+        SortKey::Index,
+    ),
+    // BungeeCord and Waterfall:
+    // proxy.log.1
+    (
+        "bungeecord",
+        r"^proxy\.log\.(?P<index>\d+)$",
+        SortKey::ReverseIndex,
+    ),
+    // logrotate:
+    // latest.log.1, latest.log.2.gz
+    (
+        "logrotate",
+        r"^latest\.log\.(?P<index>\d+)(?:\.\w+)?$",
+        SortKey::ReverseIndex,
+    ),
+];
+
+/// Naming schemes used when none are configured.
+const DEFAULT_PATTERNS: &[&str] = &["vanilla"];
+
+/// How the archives that match a pattern are ordered.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    /// By the date in their names, or if they have none, the date that they were last modified,
+    /// and then by the index, where higher indices are newer.
+    Date,
+    /// By the index, where higher indices are newer.
+    Index,
+    /// By the index, where lower indices are newer.
+    ReverseIndex,
+    /// By the time that they were last modified.
+    Modified,
+}
+
+/// A pattern of the names of archived logs, as a regex with the optional named capture groups
+/// `date`, in the form `2024-01-01`, and `index`, which is a number.
+#[derive(Clone, Debug)]
+struct ArchivePattern {
+    regex: Regex,
+    sort: SortKey,
+}
+
+/// The naming schemes of archived logs.
+#[derive(Clone, Debug)]
+pub struct ArchivePatterns {
+    patterns: Vec<ArchivePattern>,
+}
+
+/// An archived log file.
+pub struct Archive {
+    pub path: PathBuf,
+    /// Date of the logs in the file, from its name, or the date that it was last modified
+    pub date: Option<NaiveDate>,
+    /// Where the file goes when the archives are ordered
+    key: (Option<NaiveDate>, i64),
+}
+
+impl ArchivePatterns {
+    /// Find the archived logs in `dir` from the oldest to the newest, leaving out `latest` if it
+    /// is in there too.
+    pub fn find(&self, dir: &Path, latest: &Path) -> io::Result<Vec<Archive>> {
+        let latest = fs::canonicalize(latest).ok();
+        let mut archives = Vec::new();
+        for ent in fs::read_dir(dir)? {
+            let ent = ent?;
+            let name = match ent.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let (cap, sort) = match self
+                .patterns
+                .iter()
+                .find_map(|p| Some((p.regex.captures(&name)?, p.sort)))
+            {
+                Some(found) => found,
+                None => continue,
+            };
+            let path = ent.path();
+            if latest.is_some() && fs::canonicalize(&path).ok() == latest {
+                continue;
+            }
+            let named_date = cap
+                .name("date")
+                .and_then(|m| NaiveDate::parse_from_str(m.as_str(), "%Y-%m-%d").ok());
+            let index: i64 = cap
+                .name("index")
+                .and_then(|m| m.as_str().parse().ok())
+                .unwrap_or(0);
+            let modified = ent.metadata().and_then(|m| m.modified()).ok();
+            let date =
+                named_date.or_else(|| modified.map(|t| DateTime::<Local>::from(t).date_naive()));
+            // Indices order the archives by themselves, as the days that the archives were last
+            // modified may be out of order, e.g. after they were copied.
+            let key = match sort {
+                SortKey::Date => (date, index),
+                SortKey::Index => (None, index),
+                SortKey::ReverseIndex => (None, -index),
+                SortKey::Modified => (
+                    date,
+                    modified
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |d| d.as_secs() as i64),
+                ),
+            };
+            archives.push(Archive { path, date, key });
+        }
+        archives.sort_by(|a, b| a.key.cmp(&b.key).then_with(|| a.path.cmp(&b.path)));
+        Ok(archives)
+    }
+}
+
+impl Default for ArchivePatterns {
+    fn default() -> Self {
+        ArchivePatterns {
+            patterns: DEFAULT_PATTERNS
+                .iter()
+                .map(|&name| profile(name).unwrap())
+                .collect(),
+        }
+    }
+}
+
+fn profile(name: &str) -> Option<ArchivePattern> {
+    PROFILES
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|&(_, pattern, sort)| ArchivePattern {
+            regex: Regex::new(pattern).unwrap(),
+            sort,
+        })
+}
+
+/// Convert a glob, where `*` matches any text and `?` matches any character, into a regex.
+fn glob_regex(glob: &str) -> String {
+    let mut pattern = "^".to_string();
+    for c in glob.chars() {
+        match c {
+            '*' => pattern += ".*",
+            '?' => pattern += ".",
+            c => pattern += &regex::escape(&c.to_string()),
+        }
+    }
+    pattern + "$"
+}
+
+impl<'de> Deserialize<'de> for ArchivePattern {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct ArchivePatternVisitor;
+        impl<'a> Visitor<'a> for ArchivePatternVisitor {
+            type Value = ArchivePattern;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                fmt.write_str("the name of an archive pattern or a table with a regex or a glob")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                profile(s).ok_or_else(|| E::custom(format!("unknown archive pattern: '{}'", s)))
+            }
+
+            fn visit_map<A: MapAccess<'a>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                const FIELDS: &[&str] = &["regex", "glob", "sort"];
+                let mut pattern = None;
+                let mut sort = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "regex" | "glob" if pattern.is_some() => {
+                            return Err(de::Error::custom(
+                                "archive pattern has both a regex and a glob",
+                            ))
+                        }
+                        "regex" => pattern = Some(map.next_value::<String>()?),
+                        "glob" => pattern = Some(glob_regex(&map.next_value::<String>()?)),
+                        "sort" => sort = Some(map.next_value::<SortKey>()?),
+                        _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                    }
+                }
+                let pattern = pattern.ok_or_else(|| de::Error::missing_field("regex"))?;
+                let regex = Regex::new(&pattern).map_err(|e| {
+                    let msg = config::regex_error(&e);
+                    de::Error::custom(format!("invalid archive pattern regex: {}", msg))
+                })?;
+                // Archives are ordered by the date in their names if they have one.
+                let sort = sort.unwrap_or_else(|| {
+                    if regex.capture_names().any(|name| name == Some("date")) {
+                        SortKey::Date
+                    } else {
+                        SortKey::Modified
+                    }
+                });
+                Ok(ArchivePattern { regex, sort })
+            }
+        }
+        de.deserialize_any(ArchivePatternVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for ArchivePatterns {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct ArchivePatternsVisitor;
+        impl<'a> Visitor<'a> for ArchivePatternsVisitor {
+            type Value = ArchivePatterns;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                fmt.write_str("a list of archive patterns")
+            }
+
+            fn visit_seq<A: SeqAccess<'a>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut patterns = Vec::new();
+                while let Some(pattern) = seq.next_element()? {
+                    patterns.push(pattern);
+                }
+                Ok(ArchivePatterns { patterns })
+            }
+        }
+        de.deserialize_seq(ArchivePatternsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::process;
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Deserialize)]
+    struct Config {
+        archives: ArchivePatterns,
+    }
+
+    fn patterns(toml: &str) -> Result<ArchivePatterns, String> {
+        toml::from_str::<Config>(toml)
+            .map(|c| c.archives)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Find the archives in a new directory with files named `names`, some of which may be
+    /// `latest.log`. Each file was last modified a day before the one before it, so that the
+    /// days do not give the order of the archives by themselves.
+    fn find(patterns: &ArchivePatterns, test: &str, names: &[&str]) -> Vec<String> {
+        let dir = env::temp_dir().join(format!("better-console-{}-{}", process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        for (i, name) in names.iter().enumerate() {
+            let file = File::create(dir.join(name)).unwrap();
+            let day = Duration::from_secs(24 * 60 * 60);
+            file.set_modified(SystemTime::now() - day * i as u32)
+                .unwrap();
+        }
+        let archives = patterns.find(&dir, &dir.join("latest.log")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        archives
+            .into_iter()
+            .map(|a| a.path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn globs() {
+        assert_eq!(glob_regex("*.log.gz"), r"^.*\.log\.gz$");
+        assert_eq!(glob_regex("server-?.log"), r"^server\-.\.log$");
+        let regex = Regex::new(&glob_regex("logs [*].txt")).unwrap();
+        assert!(regex.is_match("logs [old].txt"));
+        assert!(!regex.is_match("logs old.txt"));
+        assert!(!regex.is_match("xlogs [old].txt"));
+    }
+
+    #[test]
+    fn by_date() {
+        let names = [
+            "2024-01-02-1.log.gz",
+            "2024-01-01-2.log.gz",
+            "2024-01-01-10.log.gz",
+            "2024-01-01-1.log",
+            "latest.log",
+            "debug-1.log.gz",
+        ];
+        let expected = [
+            "2024-01-01-1.log",
+            "2024-01-01-2.log.gz",
+            "2024-01-01-10.log.gz",
+            "2024-01-02-1.log.gz",
+        ];
+        assert_eq!(
+            find(&ArchivePatterns::default(), "by-date", &names),
+            expected
+        );
+    }
+
+    #[test]
+    fn by_index() {
+        let forge = patterns(r#"archives = ["forge-debug"]"#).unwrap();
+        let names = [
+            "debug-2.log.gz",
+            "debug-10.log.gz",
+            "debug-1.log.gz",
+            "debug.log",
+        ];
+        let expected = ["debug-1.log.gz", "debug-2.log.gz", "debug-10.log.gz"];
+        assert_eq!(find(&forge, "by-index", &names), expected);
+
+        let logrotate = patterns(r#"archives = ["logrotate"]"#).unwrap();
+        let names = [
+            "latest.log",
+            "latest.log.1",
+            "latest.log.3.gz",
+            "latest.log.2.gz",
+        ];
+        let expected = ["latest.log.3.gz", "latest.log.2.gz", "latest.log.1"];
+        assert_eq!(find(&logrotate, "by-reverse-index", &names), expected);
+    }
+
+    #[test]
+    fn tables() {
+        let archives = patterns(
+            r#"
+            archives = [
+                "bungeecord",
+                { glob = "*.old" },
+                { regex = '^(?P<date>\d{4}-\d\d-\d\d)\.txt$' },
+                { regex = '^log(?P<index>\d)$', sort = "reverse-index" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let sorts: Vec<_> = archives.patterns.iter().map(|p| p.sort).collect();
+        assert_eq!(
+            sorts,
+            [
+                SortKey::ReverseIndex,
+                SortKey::Modified,
+                SortKey::Date,
+                SortKey::ReverseIndex,
+            ]
+        );
+        assert!(archives.patterns[1].regex.is_match("a.old"));
+        assert!(!archives.patterns[1].regex.is_match("a.older"));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |toml| patterns(toml).unwrap_err();
+        assert!(error(r#"archives = ["paper"]"#).contains("unknown archive pattern: 'paper'"));
+        assert!(error(r#"archives = [{ regex = "a", glob = "b" }]"#)
+            .contains("archive pattern has both a regex and a glob"));
+        assert!(error(r#"archives = [{ glob = "*", sort = "size" }]"#).contains("unknown variant"));
+        assert!(error(r#"archives = [{ sort = "date" }]"#).contains("missing field `regex`"));
+        assert!(error(r#"archives = [{ regex = "(" }]"#).contains("invalid archive pattern regex"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "\
+usage: better-console [options]
+
+options:
+  -d, --dir <dir>               server directory (default: current directory)
+  -c, --config <file>           configuration file (default: better-console.toml)
+      --log <file>              log file to follow (default: logs/latest.log)
+      --archive-dir <dir>       directory of archived logs (default: logs)
+      --command-fifo <file>     FIFO to write commands to (default: console)
+  -h, --help                    show this help and exit
+  -V, --version                 show the version and exit
+
+Paths in the configuration file are relative to the server directory.";
+
+/// Command-line arguments. Paths are made absolute, so that they stay correct after changing to
+/// the server directory.
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    pub dir: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub log: Option<PathBuf>,
+    pub archive_dir: Option<PathBuf>,
+    pub command_fifo: Option<PathBuf>,
+}
+
+/// Parse the command-line arguments, exiting on errors and for `--help` and `--version`.
+pub fn parse() -> Args {
+    let mut args = Args::default();
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let target = match name {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-V" | "--version" => {
+                println!("better-console {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            "-d" | "--dir" => &mut args.dir,
+            "-c" | "--config" => &mut args.config,
+            "--log" => &mut args.log,
+            "--archive-dir" => &mut args.archive_dir,
+            "--command-fifo" => &mut args.command_fifo,
+            _ => fail(&format!("unknown option: '{}'", name)),
+        };
+        let value = match value.or_else(|| iter.next()) {
+            Some(value) => value,
+            None => fail(&format!("missing value for option '{}'", name)),
+        };
+        let path = env::current_dir()
+            .unwrap_or_else(|e| fail(&format!("failed to get current directory: {}", e)))
+            .join(value);
+        *target = Some(path);
+    }
+    args
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("better-console: {}", msg);
+    eprintln!("try 'better-console --help' for more information");
+    process::exit(2);
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use rustbox::RB_NORMAL;
+
+use crate::config::Color;
+
+lazy_static! {
+    /// Player chat, which is marked when it is not signed:
+    /// <Steve> hello
+    /// [Not Secure] <Steve> hello
+    static ref CHAT_REGEX: Regex =
+        Regex::new(r"^(?:\[Not Secure\] )?<(?P<name>[^>\s]+)> ").unwrap();
+    /// `/me`:
+    /// * Steve waves
+    static ref EMOTE_REGEX: Regex = Regex::new(r"^\* (?P<name>\S+) ").unwrap();
+    /// `/say`, which plugins write like too, so only the names of players and the server count:
+    /// [Steve] hello
+    static ref SAY_REGEX: Regex = Regex::new(r"^\[(?P<name>[^\]\s]+)] ").unwrap();
+    /// Whispers, which are only logged as commands, or by plugins that spy on them:
+    /// Steve issued server command: /msg Alex hello
+    /// [Steve -> Alex] hello
+    static ref WHISPER_REGEX: Regex = Regex::new(
+        r"^(?:(?P<name>\S+) issued server command: /(?:msg|tell|w|whisper|r|reply|m|t|pm) |\[(?P<spied>\S+) -> \S+] )"
+    )
+    .unwrap();
+    /// Steve joined the game
+    /// Steve left the game
+    static ref JOIN_REGEX: Regex = Regex::new(r"^(?P<name>\S+) (?:joined|left) the game$").unwrap();
+}
+
+/// Names that `/say` messages from outside of the game are written with.
+const SERVER_NAMES: &[&str] = &["Server", "Rcon", "@"];
+
+/// Find the name of the player in the message of a chat log, or return `None` if the message is
+/// not chat. `players` has the names of the players that have been seen, to tell `/say` messages
+/// apart from plugin logs.
+pub fn find_name(message: &str, players: &HashSet<String>) -> Option<Range<usize>> {
+    let regexes = [&*CHAT_REGEX, &*EMOTE_REGEX, &*WHISPER_REGEX, &*JOIN_REGEX];
+    for regex in regexes {
+        if let Some(cap) = regex.captures(message) {
+            let m = cap.name("name").or_else(|| cap.name("spied")).unwrap();
+            return Some(m.range());
+        }
+    }
+    let m = SAY_REGEX.captures(message)?.name("name")?;
+    if SERVER_NAMES.contains(&m.as_str()) || players.contains(m.as_str()) {
+        Some(m.range())
+    } else {
+        None
+    }
+}
+
+/// Get the name of the player that joined or left in the message of a log.
+pub fn joined_name(message: &str) -> Option<&str> {
+    JOIN_REGEX
+        .captures(message)
+        .and_then(|cap| cap.name("name"))
+        .map(|m| m.as_str())
+}
+
+/// Get the color of a player name. The same name always gets the same color, which is bright
+/// enough to read on a dark background.
+pub fn name_color(name: &str) -> Color {
+    // FNV-1a, so that the colors stay the same between versions of Rust
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in name.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    // Levels 2 to 5 of each channel of the color cube
+    let (r, g, b) = (hash % 4 + 2, hash / 4 % 4 + 2, hash / 16 % 4 + 2);
+    Color::new((16 + 36 * r + 6 * g + b) as u16, 0, RB_NORMAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustbox::Color as RbColor;
+
+    use super::*;
+
+    /// Find the name in a message, with `Steve` and `Alex` seen.
+    fn name(message: &str) -> Option<&str> {
+        let players = ["Steve", "Alex"].iter().map(|s| s.to_string()).collect();
+        find_name(message, &players).map(|range| &message[range])
+    }
+
+    #[test]
+    fn chat() {
+        assert_eq!(name("<Steve> hello"), Some("Steve"));
+        assert_eq!(name("[Not Secure] <Alex> hi <Steve>"), Some("Alex"));
+        assert_eq!(name("* Steve waves"), Some("Steve"));
+        assert_eq!(name("Steve joined the game"), Some("Steve"));
+        assert_eq!(name("Alex left the game"), Some("Alex"));
+    }
+
+    #[test]
+    fn whispers() {
+        assert_eq!(
+            name("Steve issued server command: /msg Alex hello"),
+            Some("Steve")
+        );
+        assert_eq!(name("[Alex -> Steve] hello"), Some("Alex"));
+        assert_eq!(
+            name("Steve issued server command: /gamemode creative"),
+            None
+        );
+    }
+
+    #[test]
+    fn say() {
+        assert_eq!(name("[Steve] hello"), Some("Steve"));
+        assert_eq!(name("[Server] restarting soon"), Some("Server"));
+        // Plugins write logs like this too.
+        assert_eq!(name("[WorldEdit] loaded"), None);
+    }
+
+    #[test]
+    fn not_chat() {
+        assert_eq!(name("Done (3.2s)! For help, type \"help\""), None);
+        assert_eq!(name("<> empty"), None);
+        assert_eq!(name("Steve joined the game again"), None);
+    }
+
+    #[test]
+    fn joined() {
+        assert_eq!(joined_name("Steve joined the game"), Some("Steve"));
+        assert_eq!(joined_name("Steve has made the advancement"), None);
+    }
+
+    #[test]
+    fn name_colors() {
+        let fg = |name| match name_color(name).fg {
+            RbColor::Byte(n) => n,
+            _ => unreachable!(),
+        };
+        assert_eq!(fg("Steve"), fg("Steve"));
+        for name in ["Steve", "Alex", "Notch", "jeb_", ""] {
+            // Levels 2 to 5 of each channel of the color cube
+            let i = fg(name) - 16;
+            assert!(i < 216);
+            assert!([i / 36, i / 6 % 6, i % 6]
+                .iter()
+                .all(|l| (2..=5).contains(l)));
+        }
+    }
+}
@@ -1,18 +1,97 @@
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
-use crossbeam::channel::Receiver;
+use crossbeam::channel::{Receiver, Sender};
 
-pub fn start(input: Receiver<String>) -> JoinHandle<io::Result<()>> {
-    thread::spawn(move || {
-        let mut file = File::create("console")?;
-        for command in input {
-            writeln!(file, "{}", command)?;
-            file.flush()?;
+use crate::config::{Transport, TransportKind};
+use crate::output::Line;
+use crate::rcon::Rcon;
+
+pub fn start(
+    transport: Transport,
+    input: Receiver<String>,
+    output: Sender<Line>,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || match transport.kind {
+        TransportKind::Fifo => {
+            let mut file = File::create(&transport.fifo)?;
+            for command in input {
+                writeln!(file, "{}", command)?;
+                file.flush()?;
+            }
+            Ok(())
+        }
+        TransportKind::Rcon => {
+            // Connect when the first command is sent, and again after errors.
+            let mut rcon = None;
+            for command in input {
+                if command.trim().is_empty() {
+                    continue;
+                }
+                let response = match send_rcon(&transport, &mut rcon, &command) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        rcon = None;
+                        format!("RCON error: {}", e)
+                    }
+                };
+                for line in response.lines() {
+                    if output.send(Line::Response(line.to_string())).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
         }
-        Ok(())
     })
 }
+
+fn send_rcon(transport: &Transport, rcon: &mut Option<Rcon>, command: &str) -> io::Result<String> {
+    let rcon = match rcon {
+        Some(rcon) => rcon,
+        None => rcon.insert(connect(transport)?),
+    };
+    rcon.command(command)
+}
+
+fn connect(transport: &Transport) -> io::Result<Rcon> {
+    let port = match transport.port {
+        Some(port) => port,
+        None => match server_property("rcon.port")? {
+            Some(port) => port.parse().map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    "invalid rcon.port in server.properties",
+                )
+            })?,
+            None => 25575,
+        },
+    };
+    let password = match &transport.password {
+        Some(password) => password.clone(),
+        None => server_property("rcon.password")?.unwrap_or_default(),
+    };
+    let timeout = Duration::from_secs(transport.timeout.max(1));
+    Rcon::connect(&format!("{}:{}", transport.host, port), &password, timeout)
+}
+
+/// Read the value of `key` from `server.properties`.
+pub fn server_property(key: &str) -> io::Result<Option<String>> {
+    let text = match fs::read_to_string("server.properties") {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let value = text
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().to_string());
+    Ok(value)
+}
@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Vanilla commands, used when no `commands.json` is available. Each line is a path through the
+/// command tree, where `a|b` are alternative literals, `<player>` is a player name, `<pos>` is a
+/// position, `<text>` is the rest of the line, `<...>` is any other single word, and `...` goes
+/// back to the root (as in `execute run`).
+const VANILLA: &str = "
+advancement grant|revoke <player> everything|only|from|through|until
+attribute <player> <attribute> get|base|modifier
+ban <player> <text>
+ban-ip <player> <text>
+banlist ips|players
+bossbar add|get|list|remove|set
+clear <player> <item>
+clone <pos> <pos> <pos>
+damage <player> <amount>
+data get|merge|modify|remove block|entity|storage
+datapack disable|enable|list
+debug start|stop|function
+defaultgamemode survival|creative|adventure|spectator
+deop <player>
+difficulty peaceful|easy|normal|hard
+effect clear|give <player> <effect>
+enchant <player> <enchantment>
+execute align|anchored|as|at|facing|if|in|on|positioned|rotated|store|summon|unless
+execute run ...
+experience|xp add|set|query <player>
+fill <pos> <pos> <block>
+forceload add|remove|query
+function <function>
+gamemode survival|creative|adventure|spectator <player>
+gamerule announceAdvancements|doDaylightCycle|doFireTick|doInsomnia|doMobSpawning|doWeatherCycle|keepInventory|mobGriefing|naturalRegeneration|playersSleepingPercentage|randomTickSpeed|showDeathMessages|spawnRadius
+give <player> <item>
+help
+item modify|replace
+jfr start|stop
+kick <player> <text>
+kill <player>
+list uuids
+locate biome|poi|structure
+loot give|insert|replace|spawn
+me <text>
+msg|tell|w <player> <text>
+op <player>
+pardon <player>
+pardon-ip <address>
+particle <particle> <pos>
+perf start|stop
+place feature|jigsaw|structure|template
+playsound <sound> <source> <player>
+publish
+recipe give|take <player>
+reload
+ride <player> mount|dismount
+save-all flush
+save-off
+save-on
+say <text>
+schedule clear|function
+scoreboard objectives add|list|modify|remove|setdisplay
+scoreboard players add|enable|get|list|operation|remove|reset|set
+seed
+setblock <pos> <block>
+setidletimeout <minutes>
+setworldspawn <pos>
+spawnpoint <player> <pos>
+spectate <player> <player>
+spreadplayers <center> <distance> <range>
+stop
+stopsound <player>
+summon <entity> <pos>
+tag <player> add|list|remove
+team add|empty|join|leave|list|modify|remove
+teammsg|tm <text>
+teleport|tp <player> <player>
+tellraw <player> <text>
+time add|query|set
+time query daytime|gametime|day
+time set day|night|noon|midnight
+title <player> clear|reset|title|subtitle|actionbar|times
+trigger <objective>
+weather clear|rain|thunder
+whitelist add|remove <player>
+whitelist list|off|on|reload
+worldborder add|center|damage|get|set|warning
+";
+
+/// A node in a Brigadier command tree, in the format of the `commands.json` report generated by
+/// the server's data generator.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Node {
+    #[serde(default)]
+    children: BTreeMap<String, Node>,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    parser: Option<String>,
+    #[serde(default)]
+    properties: BTreeMap<String, serde_json::Value>,
+    /// Path of the node to continue with after this one. An empty path is the root.
+    #[serde(default)]
+    redirect: Option<Vec<String>>,
+}
+
+impl Node {
+    fn literals(&self) -> impl Iterator<Item = (&String, &Node)> {
+        self.children.iter().filter(|(_, n)| n.kind == "literal")
+    }
+
+    fn arguments(&self) -> impl Iterator<Item = &Node> {
+        self.children.values().filter(|n| n.kind == "argument")
+    }
+}
+
+/// Completes commands from a command tree and player names seen in the logs.
+pub struct Completer {
+    root: Node,
+    players: BTreeSet<String>,
+}
+
+impl Completer {
+    /// Create a completer for the vanilla commands.
+    pub fn new() -> Completer {
+        let mut root = Node::default();
+        for line in VANILLA.lines().filter(|l| !l.is_empty()) {
+            add_path(&mut root, &line.split(' ').collect::<Vec<_>>());
+        }
+        Completer {
+            root,
+            players: BTreeSet::new(),
+        }
+    }
+
+    /// Load the command tree from a `commands.json` file generated by the server.
+    pub fn load(path: &Path) -> io::Result<Completer> {
+        let text = fs::read_to_string(path)?;
+        let root = serde_json::from_str(&text)?;
+        Ok(Completer {
+            root,
+            players: BTreeSet::new(),
+        })
+    }
+
+    /// Remember the names of players that appear in the message of a log line.
+    pub fn learn(&mut self, message: &str) {
+        lazy_static! {
+            static ref PLAYER_REGEX: Regex = Regex::new(
+                r"^(?:UUID of player (\w{1,16}) is |(\w{1,16}) (?:joined|left) the game|(\w{1,16})\[/[^]]*] logged in with entity id )"
+            )
+            .unwrap();
+        }
+        if let Some(cap) = PLAYER_REGEX.captures(message) {
+            let name = cap.iter().skip(1).flatten().next().unwrap().as_str();
+            if !self.players.contains(name) {
+                self.players.insert(name.to_string());
+            }
+        }
+    }
+
+    /// Find the completions for the last word of `line`. Returns the byte index where the last
+    /// word starts, and the candidates to replace it with.
+    pub fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let start = if start == 0 && line.starts_with('/') {
+            1
+        } else {
+            start
+        };
+        let word = &line[start..];
+        let mut words = line[..start]
+            .trim_start_matches('/')
+            .split(' ')
+            .filter(|w| !w.is_empty());
+
+        let mut node = &self.root;
+        loop {
+            node = match &node.redirect {
+                Some(path) if node.children.is_empty() => self.find(path),
+                _ => node,
+            };
+            let w = match words.next() {
+                Some(w) => w,
+                None => break,
+            };
+            if let Some(next) = node.children.get(w).filter(|n| n.kind == "literal") {
+                node = next;
+                continue;
+            }
+            node = match node.arguments().next() {
+                Some(arg) => arg,
+                None => return (start, Vec::new()),
+            };
+            match arg_words(node) {
+                // The argument takes the rest of the line.
+                None => return (start, Vec::new()),
+                Some(n) => {
+                    for _ in 1..n {
+                        if words.next().is_none() {
+                            // The word being completed is part of this argument.
+                            return (start, Vec::new());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut candidates = node
+            .literals()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(word))
+            .cloned()
+            .collect::<Vec<_>>();
+        if node.arguments().any(|n| takes_player(n.parser.as_deref())) {
+            let word = word.to_lowercase();
+            candidates.extend(
+                self.players
+                    .iter()
+                    .filter(|p| p.to_lowercase().starts_with(&word))
+                    .cloned(),
+            );
+        }
+        (start, candidates)
+    }
+
+    fn find(&self, path: &[String]) -> &Node {
+        let mut node = &self.root;
+        for name in path {
+            match node.children.get(name) {
+                Some(n) => node = n,
+                None => return &self.root,
+            }
+        }
+        node
+    }
+}
+
+/// Add the rest of a line from `VANILLA` to the tree below `node`.
+fn add_path(node: &mut Node, words: &[&str]) {
+    let (word, rest) = match words.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    if *word == "..." {
+        node.redirect = Some(Vec::new());
+    } else if let Some(name) = word.strip_prefix('<') {
+        let name = name.trim_end_matches('>');
+        let parser = match name {
+            "player" => "minecraft:game_profile",
+            "pos" => "minecraft:vec3",
+            "text" => "minecraft:message",
+            _ => "brigadier:string",
+        };
+        let child = node
+            .children
+            .entry(name.to_string())
+            .or_insert_with(|| Node {
+                kind: "argument".to_string(),
+                parser: Some(parser.to_string()),
+                ..Node::default()
+            });
+        add_path(child, rest);
+    } else {
+        for name in word.split('|') {
+            let child = node
+                .children
+                .entry(name.to_string())
+                .or_insert_with(|| Node {
+                    kind: "literal".to_string(),
+                    ..Node::default()
+                });
+            add_path(child, rest);
+        }
+    }
+}
+
+/// Get the number of words taken by an argument, or `None` if it takes the rest of the line.
+fn arg_words(node: &Node) -> Option<usize> {
+    let greedy = node.properties.get("type").and_then(|t| t.as_str()) == Some("greedy");
+    match node.parser.as_deref() {
+        Some("brigadier:string") if greedy => None,
+        Some("minecraft:message") => None,
+        Some("minecraft:vec3") | Some("minecraft:block_pos") => Some(3),
+        Some("minecraft:vec2") | Some("minecraft:column_pos") | Some("minecraft:rotation") => {
+            Some(2)
+        }
+        _ => Some(1),
+    }
+}
+
+/// Check whether an argument with the given parser can be a player name.
+fn takes_player(parser: Option<&str>) -> bool {
+    matches!(
+        parser,
+        Some("minecraft:entity") | Some("minecraft:game_profile") | Some("minecraft:score_holder")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A small command tree in the format of `commands.json`.
+    const COMMANDS_JSON: &str = r#"{
+        "type": "root",
+        "children": {
+            "tp": {
+                "type": "literal",
+                "children": {
+                    "location": {
+                        "type": "argument",
+                        "parser": "minecraft:vec3",
+                        "children": { "facing": { "type": "literal" } }
+                    }
+                }
+            },
+            "say": {
+                "type": "literal",
+                "children": {
+                    "message": { "type": "argument", "parser": "minecraft:message" }
+                }
+            },
+            "note": {
+                "type": "literal",
+                "children": {
+                    "text": {
+                        "type": "argument",
+                        "parser": "brigadier:string",
+                        "properties": { "type": "greedy" }
+                    }
+                }
+            },
+            "execute": {
+                "type": "literal",
+                "children": {
+                    "run": { "type": "literal", "redirect": [] },
+                    "as": {
+                        "type": "literal",
+                        "children": {
+                            "targets": {
+                                "type": "argument",
+                                "parser": "minecraft:entity",
+                                "redirect": ["execute"]
+                            }
+                        }
+                    }
+                }
+            },
+            "kill": {
+                "type": "literal",
+                "children": {
+                    "targets": { "type": "argument", "parser": "minecraft:entity" }
+                }
+            }
+        }
+    }"#;
+
+    fn load(json: &str) -> io::Result<Completer> {
+        // Tests run at the same time, so each one gets its own file.
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("better-console-test-{}-{}.json", process::id(), n);
+        let path = env::temp_dir().join(name);
+        fs::write(&path, json).unwrap();
+        let completer = Completer::load(&path);
+        fs::remove_file(&path).unwrap();
+        completer
+    }
+
+    /// Check that the last word of `line` starts at `start` and has the completions `expected`.
+    fn check(completer: &Completer, line: &str, start: usize, expected: &[&str]) {
+        assert_eq!(
+            completer.complete(line),
+            (start, strings(expected)),
+            "{}",
+            line
+        );
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn vanilla_literals() {
+        let completer = Completer::new();
+        check(&completer, "gamem", 0, &["gamemode"]);
+        check(&completer, "/gamem", 1, &["gamemode"]);
+        check(&completer, "gamemode s", 9, &["spectator", "survival"]);
+        check(&completer, "time query d", 11, &["day", "daytime"]);
+        check(&completer, "nothing h", 8, &[]);
+    }
+
+    #[test]
+    fn vanilla_arguments() {
+        let mut completer = Completer::new();
+        completer.learn("Steve joined the game");
+        // Players are completed where a player is expected.
+        check(&completer, "msg st", 4, &["Steve"]);
+        // The rest of the line is text.
+        check(&completer, "msg Steve st", 10, &[]);
+        check(&completer, "execute run gamem", 12, &["gamemode"]);
+    }
+
+    #[test]
+    fn load_commands() {
+        let completer = load(COMMANDS_JSON).unwrap();
+        check(&completer, "", 0, &["execute", "kill", "note", "say", "tp"]);
+        check(&completer, "gamem", 0, &[]);
+        let error = load("{ not json").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn multi_word_arguments() {
+        let completer = load(COMMANDS_JSON).unwrap();
+        check(&completer, "tp 1 ~2 ^3 f", 11, &["facing"]);
+        // The word being completed is still part of the position.
+        check(&completer, "tp 1 2 f", 7, &[]);
+    }
+
+    #[test]
+    fn greedy_arguments() {
+        let completer = load(COMMANDS_JSON).unwrap();
+        check(&completer, "say k", 4, &[]);
+        check(&completer, "say tp k", 7, &[]);
+        check(&completer, "note tp k", 8, &[]);
+    }
+
+    #[test]
+    fn redirects() {
+        let completer = load(COMMANDS_JSON).unwrap();
+        check(&completer, "execute run k", 12, &["kill"]);
+        check(&completer, "execute as @a r", 14, &["run"]);
+        check(&completer, "execute as @a run ki", 18, &["kill"]);
+    }
+
+    #[test]
+    fn players() {
+        let mut completer = load(COMMANDS_JSON).unwrap();
+        completer.learn("Steve joined the game");
+        completer.learn("UUID of player alex is 069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        completer.learn("Notch[/127.0.0.1:54321] logged in with entity id 1 at (0.5, 64.0, 0.5)");
+        completer.learn("Steve left the game");
+        completer.learn("<Steve> Herobrine joined the game");
+        check(&completer, "kill ", 5, &["Notch", "Steve", "alex"]);
+        check(&completer, "kill A", 5, &["alex"]);
+        // Players are not completed where they are not expected.
+        check(&completer, "st", 0, &[]);
+    }
+}
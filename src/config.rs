@@ -1,40 +1,251 @@
+use std::env;
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::PathBuf;
 
+use regex::Regex;
 use rustbox::{Color as RbColor, Style, RB_BOLD, RB_NORMAL, RB_REVERSE, RB_UNDERLINE};
 use serde::de;
 use serde::de::Visitor;
 use serde::{Deserialize, Deserializer};
 
+use crate::alert::Alert;
+use crate::archive::ArchivePatterns;
+use crate::format::LogFormats;
+use crate::keys::Keys;
+use crate::status::StatusTemplate;
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub colors: Colors,
+    /// Formats of log lines, tried in order.
+    pub log_formats: LogFormats,
+    /// Rules for highlighting parts of the messages of logs.
+    #[serde(deserialize_with = "deserialize_highlights")]
+    pub highlight: Vec<Highlight>,
+    /// Rules for alerting about new logs.
+    pub alert: Vec<Alert>,
+    /// What to do with formatting codes in logs.
+    pub format_codes: FormatCodes,
+    pub keys: Keys,
+    /// Log file that the server is writing to.
+    pub log_file: String,
+    /// Directory containing the archived logs.
+    pub archive_dir: String,
+    /// Names of the archived logs in `archive_dir`, and how they are ordered.
+    pub archives: ArchivePatterns,
+    /// Directory of temporary files, such as compressed archives decompressed to be read and
+    /// lines moved out of memory, or `None` for the temporary directory of the system.
+    pub spill_dir: Option<String>,
+    pub transport: Transport,
+    pub server: Server,
     pub trun_left: String,
     pub trun_right: String,
-    pub default_status: String,
+    /// Text of the status bar, with placeholders for the state of the server, such as
+    /// `{players}`, and `{keys}` for the keys of the main actions.
+    pub default_status: StatusTemplate,
     pub vertical_move: isize,
     pub horizontal_move: isize,
+    /// Whether to wrap long lines at startup instead of scrolling horizontally.
+    pub wrap: bool,
+    /// Extra indent of the rows after the first row of a wrapped line.
+    pub wrap_indent: usize,
+    /// Whether to collapse runs of logs with the same message at startup.
+    pub collapse_repeats: bool,
+    /// Whether to show the panel listing the players online at startup.
+    pub show_players: bool,
+    /// Width of the panel listing the players online.
+    pub player_panel_width: usize,
+    /// Whether to show the date of each log before its time.
+    pub show_dates: bool,
+    /// Whether to show the date and file of the topmost log on the screen in a row above the
+    /// logs.
+    pub date_header: bool,
+    /// Whether to read the state of the server, such as the players online, from the logs that
+    /// were written to the log file before starting.
+    pub scan_latest_log: bool,
+    /// Maximum number of lines kept in memory. The others are kept in a temporary file, and read
+    /// again when they are scrolled to.
+    pub max_lines: usize,
+    pub history_file: String,
+    pub history_size: usize,
+    pub commands_file: String,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             colors: Colors::default(),
+            log_formats: LogFormats::default(),
+            highlight: Vec::new(),
+            alert: Vec::new(),
+            format_codes: FormatCodes::Render,
+            keys: Keys::default(),
+            log_file: "logs/latest.log".to_string(),
+            archive_dir: "logs".to_string(),
+            archives: ArchivePatterns::default(),
+            spill_dir: None,
+            transport: Transport::default(),
+            server: Server::default(),
             trun_left: "<".to_string(),
             trun_right: ">".to_string(),
-            default_status: "ctrl-Q = quit; arrows/PgUp/PgDn/End = scroll; type to enter command"
-                .to_string(),
+            default_status: StatusTemplate::parse(
+                "{state} | {players}/{max_players} online | {scroll_pos} | {keys}",
+            )
+            .unwrap(),
             vertical_move: 1,
             horizontal_move: 16,
+            wrap: false,
+            wrap_indent: 2,
+            collapse_repeats: false,
+            show_players: false,
+            player_panel_width: 32,
+            show_dates: false,
+            date_header: true,
+            scan_latest_log: true,
+            max_lines: 100_000,
+            history_file: ".better-console_history".to_string(),
+            history_size: 1000,
+            commands_file: "generated/reports/commands.json".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Get the directory of temporary files.
+    pub fn spill_dir(&self) -> PathBuf {
+        match &self.spill_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => env::temp_dir(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Transport {
+    pub kind: TransportKind,
+    /// Path of the FIFO to write commands to.
+    pub fifo: String,
+    pub host: String,
+    /// RCON port, or `None` to use `rcon.port` from `server.properties`.
+    pub port: Option<u16>,
+    /// RCON password, or `None` to use `rcon.password` from `server.properties`.
+    pub password: Option<String>,
+    /// Seconds to wait for the server to accept an RCON connection or answer a command.
+    pub timeout: u64,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport {
+            kind: TransportKind::Fifo,
+            fifo: "console".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: None,
+            password: None,
+            timeout: 10,
+        }
+    }
+}
+
+/// Settings for running the server as a child process.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Server {
+    /// Command to start the server with. If empty, the server is not started, and the logs and
+    /// the command transport are used to talk to a server that is already running.
+    pub command: Vec<String>,
+    /// Command that stops the server.
+    pub stop_command: String,
+    /// Seconds to wait for the server to stop when quitting before killing it.
+    pub stop_timeout: u64,
+    /// Command that starts the server again after it stopped. It is not sent to the server.
+    pub start_command: String,
+    /// Whether to restart the server when it exits without being stopped by a command.
+    pub auto_restart: bool,
+    /// Seconds to wait before restarting the server the first time.
+    pub restart_delay: u64,
+    /// Maximum number of seconds to wait before restarting the server.
+    pub max_restart_delay: u64,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            command: Vec::new(),
+            stop_command: "stop".to_string(),
+            stop_timeout: 60,
+            start_command: "start".to_string(),
+            auto_restart: false,
+            restart_delay: 5,
+            max_restart_delay: 300,
         }
     }
 }
 
+/// How commands are sent to the server.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// Write commands to a FIFO that is read by a wrapper script.
+    Fifo,
+    /// Send commands through RCON.
+    Rcon,
+}
+
+/// A rule for highlighting the parts of messages that match a regex.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Highlight {
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub regex: Regex,
+    pub color: Color,
+    /// Where matches of several rules overlap, the rule with the highest priority is used, or the
+    /// first one of them in the configuration if they have the same priority.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Deserialize the highlight rules, ordered from the lowest to the highest priority, so that each
+/// rule can be applied over the ones before it.
+fn deserialize_highlights<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<Highlight>, D::Error> {
+    let mut rules = Vec::<Highlight>::deserialize(de)?;
+    rules.reverse();
+    // Stable, so the first rule in the configuration is last among rules of the same priority.
+    rules.sort_by_key(|rule| rule.priority);
+    Ok(rules)
+}
+
+pub fn deserialize_regex<'de, D: Deserializer<'de>>(de: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(de)?;
+    Regex::new(&pattern)
+        .map_err(|e| de::Error::custom(format!("invalid regex '{}': {}", pattern, regex_error(&e))))
+}
+
+/// Get what is wrong with a regex from an error, which is the last line of its message. The lines
+/// before it repeat the regex.
+pub fn regex_error(e: &regex::Error) -> String {
+    let msg = e.to_string();
+    msg.lines().last().unwrap_or_default().trim().to_string()
+}
+
+/// What to do with Minecraft formatting codes (such as `§a`) and ANSI escape sequences.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatCodes {
+    /// Show the text in the colors and styles given by the codes.
+    Render,
+    /// Remove the codes and show the text in the usual colors.
+    Strip,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(default)]
 pub struct Colors {
     pub command: Color,
+    pub response: Color,
+    pub notice: Color,
     pub file_header: Color,
     pub time: Color,
     pub info: Color,
@@ -45,14 +256,26 @@ pub struct Colors {
     pub other: Color,
     pub text: Color,
     pub truncate: Color,
+    /// Summary of a collapsed stack trace, and count of collapsed repeated logs.
+    pub collapsed: Color,
+    pub search: Color,
+    pub popup: Color,
+    pub popup_selected: Color,
     pub prompt: Color,
     pub status: Color,
+    /// Status bar showing a log that triggered an alert.
+    pub alert: Color,
+    /// Panel listing the players online.
+    pub panel: Color,
+    pub panel_title: Color,
 }
 
 impl Default for Colors {
     fn default() -> Self {
         Colors {
             command: Color::new(0, 0, RB_BOLD),
+            response: Color::new(0, 0, RB_NORMAL),
+            notice: Color::new(0, 0, RB_BOLD),
             file_header: Color::new(0, 0, RB_BOLD),
             time: Color::new(0, 0, RB_NORMAL),
             info: Color::new(0, 0, RB_NORMAL),
@@ -63,8 +286,15 @@ impl Default for Colors {
             other: Color::new(0, 0, RB_NORMAL),
             text: Color::new(0, 0, RB_NORMAL),
             truncate: Color::new(0, 0, RB_REVERSE),
+            collapsed: Color::new(0, 0, RB_REVERSE),
+            search: Color::new(0, 0, RB_REVERSE),
+            popup: Color::new(0, 0, RB_REVERSE),
+            popup_selected: Color::new(0, 0, RB_BOLD),
             prompt: Color::new(0, 0, RB_REVERSE),
             status: Color::new(0, 0, RB_BOLD),
+            alert: Color::new(0, 0, RB_BOLD | RB_REVERSE),
+            panel: Color::new(0, 0, RB_NORMAL),
+            panel_title: Color::new(0, 0, RB_BOLD),
         }
     }
 }
@@ -158,3 +388,40 @@ impl<'de> Deserialize<'de> for Color {
         de.deserialize_str(ColorVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_priorities() {
+        let config: Config = toml::from_str(
+            r#"
+            [[highlight]]
+            regex = "a"
+            color = "1"
+
+            [[highlight]]
+            regex = "b"
+            color = "2"
+            priority = 5
+
+            [[highlight]]
+            regex = "c"
+            color = "3"
+            "#,
+        )
+        .unwrap();
+        let order: Vec<_> = config.highlight.iter().map(|r| r.regex.as_str()).collect();
+        // The first of the rules with the same priority is applied last, so that it wins.
+        assert_eq!(order, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn invalid_highlight_regex() {
+        let error = toml::from_str::<Config>("[[highlight]]\nregex = \"(\"\ncolor = \"1\"")
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("invalid regex '('"));
+    }
+}
@@ -0,0 +1,191 @@
+use rustbox::Key;
+
+use crate::input::Input;
+
+/// A single line of editable text with a cursor, using Emacs-style key bindings.
+#[derive(Clone, Debug, Default)]
+pub struct Editor {
+    text: String,
+    /// Byte index of the cursor in `text`.
+    cursor: usize,
+    /// Text most recently removed with a kill command.
+    yanked: String,
+    /// Column of `text` shown at the left edge of the screen.
+    scroll: usize,
+}
+
+impl Editor {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replace the text, moving the cursor to the end.
+    pub fn set_text(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// Get the text before the cursor.
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    /// Replace the text between byte index `start` and the cursor with `text`, moving the cursor
+    /// to the end of the new text.
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        self.text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Get the column on the screen of byte index `index`, relative to the start of the text
+    /// returned by `visible`.
+    pub fn column(&self, index: usize) -> usize {
+        self.text[..index]
+            .chars()
+            .count()
+            .saturating_sub(self.scroll)
+    }
+
+    /// Remove and return the text.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.scroll = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    /// Process an editing key. Returns `false` if the key is not an editing key.
+    pub fn process(&mut self, input: Input) -> bool {
+        match input {
+            Input::Key(Key::Char(c)) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            Input::Key(Key::Left) => self.cursor = self.prev_char(),
+            Input::Key(Key::Right) => self.cursor = self.next_char(),
+            Input::Key(Key::Home) | Input::Key(Key::Ctrl('a')) => self.cursor = 0,
+            Input::Key(Key::End) | Input::Key(Key::Ctrl('e')) => self.cursor = self.text.len(),
+            Input::Alt(Key::Char('b')) => self.cursor = self.prev_word(),
+            Input::Alt(Key::Char('f')) => self.cursor = self.next_word(),
+            Input::Key(Key::Backspace) => {
+                let start = self.prev_char();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            Input::Key(Key::Delete) | Input::Key(Key::Ctrl('d')) => {
+                let end = self.next_char();
+                self.text.replace_range(self.cursor..end, "");
+            }
+            Input::Key(Key::Ctrl('w')) => {
+                // Like readline, Ctrl-W removes everything up to the previous whitespace.
+                let before = self.text[..self.cursor].trim_end();
+                let start = before
+                    .char_indices()
+                    .rev()
+                    .find(|(_, c)| c.is_whitespace())
+                    .map_or(0, |(i, c)| i + c.len_utf8());
+                self.kill(start, self.cursor);
+            }
+            Input::Alt(Key::Backspace) => self.kill(self.prev_word(), self.cursor),
+            Input::Alt(Key::Char('d')) => self.kill(self.cursor, self.next_word()),
+            Input::Key(Key::Ctrl('u')) => self.kill(0, self.cursor),
+            Input::Key(Key::Ctrl('k')) => self.kill(self.cursor, self.text.len()),
+            Input::Key(Key::Ctrl('y')) => {
+                self.text.insert_str(self.cursor, &self.yanked);
+                self.cursor += self.yanked.len();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Get the part of the text that fits in `width` columns, keeping the cursor on the screen,
+    /// along with the column of the cursor relative to the returned text.
+    pub fn visible(&mut self, width: usize) -> (&str, usize) {
+        let col = self.text[..self.cursor].chars().count();
+        if col < self.scroll {
+            self.scroll = col;
+        } else if width > 0 && col >= self.scroll + width {
+            self.scroll = col + 1 - width;
+        }
+        let start = self
+            .text
+            .char_indices()
+            .nth(self.scroll)
+            .map_or(self.text.len(), |(i, _)| i);
+        (&self.text[start..], col - self.scroll)
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.yanked = self.text[start..end].to_string();
+            self.text.replace_range(start..end, "");
+            self.cursor = start;
+        }
+    }
+
+    fn prev_char(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_char(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    /// Get the start of the word before the cursor.
+    fn prev_word(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end_matches(|c: char| !c.is_alphanumeric());
+        before
+            .rfind(|c: char| !c.is_alphanumeric())
+            .map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8())
+    }
+
+    /// Get the end of the word after the cursor.
+    fn next_word(&self) -> usize {
+        let after = &self.text[self.cursor..];
+        let skipped = after.len()
+            - after
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .len();
+        let word = &after[skipped..];
+        let len = word
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(word.len());
+        self.cursor + skipped + len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl_w(text: &str) -> String {
+        let mut editor = Editor::default();
+        editor.set_text(text.to_string());
+        editor.process(Input::Key(Key::Ctrl('w')));
+        editor.take()
+    }
+
+    #[test]
+    fn ctrl_w_removes_the_last_word() {
+        assert_eq!(ctrl_w("say hello world"), "say hello ");
+        assert_eq!(ctrl_w("say hello  "), "say ");
+        assert_eq!(ctrl_w("hello"), "");
+    }
+
+    #[test]
+    fn ctrl_w_after_multibyte_whitespace() {
+        assert_eq!(ctrl_w("say\u{a0}hello"), "say\u{a0}");
+        assert_eq!(ctrl_w("say\u{3000}こんにちは"), "say\u{3000}");
+        assert_eq!(ctrl_w("say hello\u{3000}"), "say ");
+    }
+}
@@ -0,0 +1,163 @@
+use regex::Regex;
+
+use crate::config;
+use crate::format::LogFormats;
+use crate::output::Line;
+
+/// Log levels from least to most severe.
+const LEVELS: &[&str] = &["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "SEVERE", "FATAL"];
+
+/// A filter that decides which lines are shown.
+///
+/// Filters are written as space-separated terms, which must all match for a line to be shown:
+///
+/// - `level:WARN+` matches lines with level WARN or above;
+/// - `level:INFO,ERROR` matches lines with level INFO or ERROR;
+/// - `thread:main` matches lines logged by the thread `main`, and `thread:Worker*` matches the
+///   threads whose names start with `Worker` (use quotes for names containing spaces, e.g.
+///   `thread:"Server thread"`);
+/// - `logger:net.minecraft*` matches the lines logged by loggers whose names start with
+///   `net.minecraft`, in the log formats that include the logger;
+/// - everything else is joined with spaces into a regex that must match the line.
+///
+/// File headers are always shown.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The filter as typed by the user.
+    pub text: String,
+    levels: Option<Vec<&'static str>>,
+    thread: Option<String>,
+    logger: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let mut levels = None;
+        let mut thread = None;
+        let mut logger = None;
+        let mut pattern = Vec::new();
+        for term in split_terms(text)? {
+            if let Some(value) = term.strip_prefix("level:") {
+                levels = Some(parse_levels(value)?);
+            } else if let Some(value) = term.strip_prefix("thread:") {
+                thread = Some(value.to_string());
+            } else if let Some(value) = term.strip_prefix("logger:") {
+                logger = Some(value.to_string());
+            } else {
+                pattern.push(term);
+            }
+        }
+        let regex = if pattern.is_empty() {
+            None
+        } else {
+            let pattern = pattern.join(" ");
+            match Regex::new(&pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => return Err(format!("invalid regex: {}", config::regex_error(&e))),
+            }
+        };
+        Ok(Filter {
+            text: text.trim().to_string(),
+            levels,
+            thread,
+            logger,
+            regex,
+        })
+    }
+
+    /// Check whether `line` should be shown.
+    pub fn matches(&self, line: &Line, formats: &LogFormats) -> bool {
+        let s = match line {
+            Line::Log(s, _) | Line::Response(s) => s,
+            Line::Header(_) | Line::Notice(_) => return true,
+        };
+        if self.levels.is_some() || self.thread.is_some() || self.logger.is_some() {
+            let log = match formats.parse(s) {
+                Some(log) => log,
+                None => return false,
+            };
+            if let Some(levels) = &self.levels {
+                if !has_level(levels, log.level) {
+                    return false;
+                }
+            }
+            if let Some(thread) = &self.thread {
+                if !matches_name(thread, log.thread) {
+                    return false;
+                }
+            }
+            if let Some(logger) = &self.logger {
+                if !matches_name(logger, log.logger) {
+                    return false;
+                }
+            }
+        }
+        match &self.regex {
+            Some(regex) => regex.is_match(s),
+            None => true,
+        }
+    }
+}
+
+/// Check whether `name` is `pattern`, or starts with it if it ends with `*`.
+fn matches_name(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Check whether `level` is one of `levels`.
+pub fn has_level(levels: &[&str], level: &str) -> bool {
+    // java.util.logging calls it WARNING.
+    let level = match level {
+        "WARNING" => "WARN",
+        level => level,
+    };
+    levels.contains(&level)
+}
+
+/// Parse a list of levels, such as `WARN+` for WARN and above, or `INFO,ERROR`.
+pub fn parse_levels(value: &str) -> Result<Vec<&'static str>, String> {
+    let find = |name: &str| {
+        LEVELS
+            .iter()
+            .position(|l| l.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown level: '{}'", name))
+    };
+    if let Some(name) = value.strip_suffix('+') {
+        Ok(LEVELS[find(name)?..].to_vec())
+    } else {
+        value
+            .split(',')
+            .map(|name| find(name).map(|i| LEVELS[i]))
+            .collect()
+    }
+}
+
+/// Split `text` at spaces that are not inside double quotes, removing the quotes.
+fn split_terms(text: &str) -> Result<Vec<String>, String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if !term.is_empty() {
+                    terms.push(term);
+                    term = String::new();
+                }
+            }
+            _ => term.push(ch),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    Ok(terms)
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use regex::Regex;
+use serde::de;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::config;
+
+/// Built-in log formats, which can be used in the configuration by name.
+const PROFILES: &[(&str, &str)] = &[
+    // Vanilla, Fabric, Paper and Spigot log files, and Forge's latest.log:
+    // [14:02:11] [Server thread/INFO] [net.minecraftforge.Logger/]: message
+    (
+        "vanilla",
+        r"^\[(?P<time>\d\d:\d\d:\d\d)] \[(?P<thread>[^]/]+)/(?P<level>[A-Z]+)](?: \[(?P<logger>[^]]*)])*: (?P<message>.*)$",
+    ),
+    // Forge's debug.log:
+    // [01Jan2024 14:02:11.123] [main/INFO] [cpw.mods.modlauncher.Launcher/MODLAUNCHER]: message
+    (
+        "forge",
+        r"^\[(?P<time>\d\d\w{3}\d{4} \d\d:\d\d:\d\d\.\d+)] \[(?P<thread>[^]]+?)/(?P<level>[A-Z]+)] \[(?P<logger>[^]]*)]: (?P<message>.*)$",
+    ),
+    // The console of Paper, Spigot and Velocity:
+    // [14:02:11 INFO]: message
+    // [14:02:11 INFO] [velocity]: message
+    (
+        "console",
+        r"^\[(?P<time>\d\d:\d\d:\d\d) (?P<level>[A-Z]+)](?: \[(?P<logger>[^]]*)])?: (?P<message>.*)$",
+    ),
+    // BungeeCord and Waterfall:
+    // 14:02:11 [INFO] message
+    (
+        "bungeecord",
+        r"^(?P<time>\d\d:\d\d:\d\d) \[(?P<level>[A-Z]+)] (?P<message>.*)$",
+    ),
+    // Bedrock Dedicated Server:
+    // [2024-01-01 12:00:00:123 INFO] message
+    (
+        "bedrock",
+        r"^\[(?P<time>\d{4}-\d\d-\d\d \d\d:\d\d:\d\d(?::\d+)?) (?P<level>[A-Z]+)] (?P<message>.*)$",
+    ),
+    // Log4j's default pattern with full dates:
+    // 2024-01-01 12:00:00,123 [main] INFO  org.example.Logger - message
+    (
+        "log4j",
+        r"^(?P<time>\d{4}-\d\d-\d\d[ T]\d\d:\d\d:\d\d(?:[.,]\d+)?) \[(?P<thread>[^]]*)] (?P<level>[A-Z]+) +(?P<logger>\S+) - (?P<message>.*)$",
+    ),
+];
+
+/// Formats used when none are configured.
+const DEFAULT_FORMATS: &[&str] = &["vanilla", "console"];
+
+/// The parts of a log line. The parts that are missing from the format of the line are empty.
+pub struct Log<'a> {
+    pub time: &'a str,
+    pub thread: &'a str,
+    pub level: &'a str,
+    pub logger: &'a str,
+    pub message: &'a str,
+}
+
+/// The formats of log lines, as regexes with named capture groups `time`, `thread`, `level`,
+/// `logger` and `message`. Only `message` is required.
+#[derive(Clone, Debug)]
+pub struct LogFormats {
+    formats: Vec<Regex>,
+}
+
+impl LogFormats {
+    /// Split a log line into its parts using the first format that matches it, or return `None`
+    /// if it is not in any of the formats.
+    pub fn parse<'a>(&self, log: &'a str) -> Option<Log<'a>> {
+        let cap = self.formats.iter().find_map(|regex| regex.captures(log))?;
+        let get = |name| cap.name(name).map_or("", |m| m.as_str());
+        Some(Log {
+            time: get("time"),
+            thread: get("thread"),
+            level: get("level"),
+            logger: get("logger"),
+            message: get("message"),
+        })
+    }
+}
+
+impl Default for LogFormats {
+    fn default() -> Self {
+        LogFormats {
+            formats: DEFAULT_FORMATS
+                .iter()
+                .map(|&name| profile(name).unwrap())
+                .collect(),
+        }
+    }
+}
+
+fn profile(name: &str) -> Option<Regex> {
+    PROFILES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, pattern)| Regex::new(pattern).unwrap())
+}
+
+/// A log format in the configuration: either the name of a built-in format, or a table with the
+/// regex of a custom format.
+struct LogFormat(Regex);
+
+impl<'de> Deserialize<'de> for LogFormat {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct LogFormatVisitor;
+        impl<'a> Visitor<'a> for LogFormatVisitor {
+            type Value = LogFormat;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                fmt.write_str("the name of a log format or a table with a regex")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                match profile(s) {
+                    Some(regex) => Ok(LogFormat(regex)),
+                    None => Err(E::custom(format!("unknown log format: '{}'", s))),
+                }
+            }
+
+            fn visit_map<A: MapAccess<'a>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut pattern = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "regex" => pattern = Some(map.next_value::<String>()?),
+                        _ => return Err(de::Error::unknown_field(&key, &["regex"])),
+                    }
+                }
+                let pattern = pattern.ok_or_else(|| de::Error::missing_field("regex"))?;
+                let regex = Regex::new(&pattern).map_err(|e| {
+                    let msg = config::regex_error(&e);
+                    de::Error::custom(format!("invalid log format regex: {}", msg))
+                })?;
+                if !regex.capture_names().any(|name| name == Some("message")) {
+                    return Err(de::Error::custom(format!(
+                        "log format regex has no 'message' group: '{}'",
+                        pattern
+                    )));
+                }
+                Ok(LogFormat(regex))
+            }
+        }
+        de.deserialize_any(LogFormatVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogFormats {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct LogFormatsVisitor;
+        impl<'a> Visitor<'a> for LogFormatsVisitor {
+            type Value = LogFormats;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                fmt.write_str("a list of log formats")
+            }
+
+            fn visit_seq<A: SeqAccess<'a>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut formats = Vec::new();
+                while let Some(LogFormat(regex)) = seq.next_element()? {
+                    formats.push(regex);
+                }
+                Ok(LogFormats { formats })
+            }
+        }
+        de.deserialize_seq(LogFormatsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        log_formats: LogFormats,
+    }
+
+    fn formats(toml: &str) -> Result<LogFormats, String> {
+        toml::from_str::<Config>(toml)
+            .map(|c| c.log_formats)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parse a line with a built-in format into its time, thread, level, logger and message.
+    fn parse<'a>(name: &str, line: &'a str) -> Option<[&'a str; 5]> {
+        let formats = LogFormats {
+            formats: vec![profile(name).unwrap()],
+        };
+        let log = formats.parse(line)?;
+        Some([log.time, log.thread, log.level, log.logger, log.message])
+    }
+
+    #[test]
+    fn vanilla() {
+        assert_eq!(
+            parse("vanilla", "[14:02:11] [Server thread/INFO]: Done (3.2s)!"),
+            Some(["14:02:11", "Server thread", "INFO", "", "Done (3.2s)!"])
+        );
+        assert_eq!(
+            parse(
+                "vanilla",
+                "[14:02:11] [Server thread/INFO] [net.minecraftforge.Logger/]: message"
+            ),
+            Some([
+                "14:02:11",
+                "Server thread",
+                "INFO",
+                "net.minecraftforge.Logger/",
+                "message"
+            ])
+        );
+        assert_eq!(parse("vanilla", "\tat java.lang.Thread.run"), None);
+    }
+
+    #[test]
+    fn forge() {
+        assert_eq!(
+            parse(
+                "forge",
+                "[01Jan2024 14:02:11.123] [main/INFO] [cpw.mods.modlauncher.Launcher/MODLAUNCHER]: message"
+            ),
+            Some([
+                "01Jan2024 14:02:11.123",
+                "main",
+                "INFO",
+                "cpw.mods.modlauncher.Launcher/MODLAUNCHER",
+                "message"
+            ])
+        );
+    }
+
+    #[test]
+    fn console() {
+        assert_eq!(
+            parse("console", "[14:02:11 INFO]: message"),
+            Some(["14:02:11", "", "INFO", "", "message"])
+        );
+        assert_eq!(
+            parse("console", "[14:02:11 WARN] [velocity]: message"),
+            Some(["14:02:11", "", "WARN", "velocity", "message"])
+        );
+    }
+
+    #[test]
+    fn bungeecord() {
+        assert_eq!(
+            parse("bungeecord", "14:02:11 [INFO] message"),
+            Some(["14:02:11", "", "INFO", "", "message"])
+        );
+    }
+
+    #[test]
+    fn bedrock() {
+        assert_eq!(
+            parse("bedrock", "[2024-01-01 12:00:00:123 INFO] message"),
+            Some(["2024-01-01 12:00:00:123", "", "INFO", "", "message"])
+        );
+    }
+
+    #[test]
+    fn log4j() {
+        assert_eq!(
+            parse(
+                "log4j",
+                "2024-01-01 12:00:00,123 [main] INFO  org.example.Logger - message - with dashes"
+            ),
+            Some([
+                "2024-01-01 12:00:00,123",
+                "main",
+                "INFO",
+                "org.example.Logger",
+                "message - with dashes"
+            ])
+        );
+    }
+
+    #[test]
+    fn in_order() {
+        let formats = formats(
+            r#"log_formats = ["bungeecord", { regex = '^(?P<level>\w+): (?P<message>.*)$' }]"#,
+        )
+        .unwrap();
+        let log = formats.parse("14:02:11 [INFO] a: b").unwrap();
+        assert_eq!((log.level, log.message), ("INFO", "a: b"));
+        let log = formats.parse("WARN: a").unwrap();
+        assert_eq!((log.time, log.level, log.message), ("", "WARN", "a"));
+        assert!(formats.parse("[14:02:11 INFO]: message").is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |toml| formats(toml).unwrap_err();
+        assert!(error(r#"log_formats = ["paper"]"#).contains("unknown log format: 'paper'"));
+        assert!(error(r#"log_formats = [{ regex = '^(?P<msg>.*)$' }]"#)
+            .contains("log format regex has no 'message' group"));
+        assert!(error(r#"log_formats = [{ regex = "(" }]"#).contains("invalid log format regex"));
+        assert!(error(r#"log_formats = [{ pattern = ".*" }]"#).contains("unknown field"));
+    }
+}
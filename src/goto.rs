@@ -0,0 +1,194 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::format::LogFormats;
+
+lazy_static! {
+    /// 2024-05-03, 2024-05-03 14:20 or 2024-05-03 14:20:15
+    static ref DATE_REGEX: Regex =
+        Regex::new(r"^(\d{4})-(\d\d)-(\d\d)(?:[ T](\d\d?:\d\d(?::\d\d)?))?$").unwrap();
+    /// 14:20 or 14:20:15
+    static ref TIME_REGEX: Regex = Regex::new(r"^\d\d?:\d\d(?::\d\d)?$").unwrap();
+    /// -2h, -1h30m or -90s
+    static ref RELATIVE_REGEX: Regex =
+        Regex::new(r"^-(?:(\d+)d)?(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s)?$").unwrap();
+    static ref CLOCK_REGEX: Regex = Regex::new(r"\d\d:\d\d:\d\d").unwrap();
+}
+
+/// Parse the time to go to, which is a date with an optional time, a time today, or how long ago
+/// from `now`, like `2024-05-03 14:20`, `14:20` or `-2h`.
+pub fn parse_target(text: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid time: '{}'", text);
+    if let Some(cap) = DATE_REGEX.captures(text) {
+        let num = |i: usize| -> u32 { cap[i].parse().unwrap() };
+        let date = NaiveDate::from_ymd_opt(num(1) as i32, num(2), num(3)).ok_or_else(invalid)?;
+        let time = match cap.get(4) {
+            Some(m) => parse_time(m.as_str()).ok_or_else(invalid)?,
+            None => NaiveTime::MIN,
+        };
+        Ok(date.and_time(time))
+    } else if TIME_REGEX.is_match(text) {
+        let time = parse_time(text).ok_or_else(invalid)?;
+        Ok(now.date().and_time(time))
+    } else if let Some(cap) = RELATIVE_REGEX.captures(text) {
+        if text == "-" {
+            return Err(invalid());
+        }
+        let mut seconds: i64 = 0;
+        for (i, unit) in [(1, 86400), (2, 3600), (3, 60), (4, 1)] {
+            if let Some(m) = cap.get(i) {
+                let n: i64 = m.as_str().parse().map_err(|_| invalid())?;
+                seconds = n
+                    .checked_mul(unit)
+                    .and_then(|s| seconds.checked_add(s))
+                    .ok_or_else(invalid)?;
+            }
+        }
+        Duration::try_seconds(seconds)
+            .and_then(|ago| now.checked_sub_signed(ago))
+            .ok_or_else(invalid)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Parse a time like `14:20` or `14:20:15`.
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    let mut parts = text.split(':').map(|p| p.parse().ok());
+    let (h, m) = (parts.next()??, parts.next()??);
+    let s = parts.next().flatten().unwrap_or(0);
+    NaiveTime::from_hms_opt(h, m, s)
+}
+
+/// Get the time of day of a log line, or `None` if it is not in a log format with a time.
+pub fn clock(log: &str, formats: &LogFormats) -> Option<NaiveTime> {
+    let time = formats.parse(log)?.time;
+    NaiveTime::parse_from_str(CLOCK_REGEX.find(time)?.as_str(), "%H:%M:%S").ok()
+}
+
+/// Works out the dates of log lines, which usually only have the time of day, by counting the
+/// times that the day changes between them.
+pub struct Dater {
+    date: NaiveDate,
+    last: Option<NaiveTime>,
+    /// Whether the lines are read from the newest one to the oldest one.
+    backwards: bool,
+}
+
+impl Dater {
+    /// Start at the given date. If `last` is given, the first line is compared with it.
+    pub fn new(date: NaiveDate, last: Option<NaiveTime>, backwards: bool) -> Dater {
+        Dater {
+            date,
+            last,
+            backwards,
+        }
+    }
+
+    /// Get the date and time of the next line, given its time of day.
+    pub fn next(&mut self, clock: NaiveTime) -> NaiveDateTime {
+        if let Some(last) = self.last {
+            // Lines from different threads can be a little out of order, so only a big jump
+            // means that the day changed.
+            let half_day = Duration::hours(12);
+            if !self.backwards && last - clock > half_day {
+                self.date = self.date.succ_opt().unwrap_or(self.date);
+            } else if self.backwards && clock - last > half_day {
+                self.date = self.date.pred_opt().unwrap_or(self.date);
+            }
+        }
+        self.last = Some(clock);
+        self.date.and_time(clock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn dates() {
+        let now = at("2024-05-03 15:00:00");
+        let parse = |text| parse_target(text, now).unwrap();
+        assert_eq!(parse("2024-05-01"), at("2024-05-01 00:00:00"));
+        assert_eq!(parse("2024-05-01 14:20"), at("2024-05-01 14:20:00"));
+        assert_eq!(parse(" 2024-05-01T14:20:15 "), at("2024-05-01 14:20:15"));
+    }
+
+    #[test]
+    fn times_today() {
+        let now = at("2024-05-03 15:00:00");
+        assert_eq!(parse_target("14:20", now), Ok(at("2024-05-03 14:20:00")));
+        assert_eq!(parse_target("9:05:30", now), Ok(at("2024-05-03 09:05:30")));
+    }
+
+    #[test]
+    fn relative() {
+        let now = at("2024-05-03 15:00:00");
+        assert_eq!(parse_target("-2h", now), Ok(at("2024-05-03 13:00:00")));
+        assert_eq!(parse_target("-1d1h30m", now), Ok(at("2024-05-02 13:30:00")));
+        assert_eq!(parse_target("-90s", now), Ok(at("2024-05-03 14:58:30")));
+    }
+
+    #[test]
+    fn invalid() {
+        let now = at("2024-05-03 15:00:00");
+        for text in [
+            "",
+            "-",
+            "yesterday",
+            "2024-02-30",
+            "2024-05-01 25:00",
+            "14:60",
+            "-99999999999999999d",
+        ] {
+            assert!(parse_target(text, now).is_err(), "{}", text);
+        }
+        assert_eq!(
+            parse_target("soon", now),
+            Err("invalid time: 'soon'".to_string())
+        );
+    }
+
+    #[test]
+    fn clocks() {
+        let formats = LogFormats::default();
+        let clock = |log| clock(log, &formats);
+        assert_eq!(
+            clock("[14:20:15] [Server thread/INFO]: hi"),
+            Some(time("14:20:15"))
+        );
+        assert_eq!(clock("[14:20:15 INFO]: hi"), Some(time("14:20:15")));
+        assert_eq!(clock("\tat net.minecraft.Main"), None);
+    }
+
+    #[test]
+    fn dater_forwards() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 3).unwrap();
+        let mut dater = Dater::new(date, None, false);
+        assert_eq!(dater.next(time("23:59:00")), at("2024-05-03 23:59:00"));
+        // Slightly out of order lines are on the same day.
+        assert_eq!(dater.next(time("23:58:59")), at("2024-05-03 23:58:59"));
+        assert_eq!(dater.next(time("00:00:01")), at("2024-05-04 00:00:01"));
+        assert_eq!(dater.next(time("12:00:00")), at("2024-05-04 12:00:00"));
+    }
+
+    #[test]
+    fn dater_backwards() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 4).unwrap();
+        let mut dater = Dater::new(date, Some(time("00:10:00")), true);
+        assert_eq!(dater.next(time("00:05:00")), at("2024-05-04 00:05:00"));
+        assert_eq!(dater.next(time("23:55:00")), at("2024-05-03 23:55:00"));
+        assert_eq!(dater.next(time("23:56:00")), at("2024-05-03 23:56:00"));
+    }
+}
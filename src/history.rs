@@ -1,75 +1,911 @@
 use std::fs;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crossbeam::channel::{Receiver, select, Sender};
+use bzip2::read::BzDecoder;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use crossbeam::channel::{select, Receiver, Sender, TryRecvError};
 use flate2::read::GzDecoder;
-use lazy_static::lazy_static;
-use regex::Regex;
+use xz2::read::XzDecoder;
 
-use crate::output::Line;
+use crate::archive::ArchivePatterns;
+use crate::format::LogFormats;
+use crate::goto;
+use crate::goto::Dater;
+use crate::output::{Line, LogFile};
+use crate::status::ServerState;
 
-pub fn start(input: Receiver<()>, output: Sender<Line>) -> JoinHandle<io::Result<()>> {
+/// Size of the blocks that files are read backwards in.
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Number of decompressed archives to keep, so that going back to them does not decompress them
+/// again.
+const MAX_SPILLS: usize = 4;
+
+/// Number of names to try for a temporary file before giving up.
+const MAX_TEMP_TRIES: usize = 100;
+
+/// Number of lines at the start of an archive to look for a time in.
+const FIRST_TIME_LINES: usize = 1000;
+
+/// Reads the decompressed contents of a compressed file.
+type Decompressor = fn(File) -> io::Result<Box<dyn Read>>;
+
+/// Decompressors of archives by the extensions of their names. Archives with other extensions are
+/// read as they are.
+const DECOMPRESSORS: &[(&str, Decompressor)] = &[
+    ("gz", |file| Ok(Box::new(GzDecoder::new(file)))),
+    ("bz2", |file| Ok(Box::new(BzDecoder::new(file)))),
+    ("xz", |file| Ok(Box::new(XzDecoder::new(file)))),
+    ("zst", |file| Ok(Box::new(zstd::Decoder::new(file)?))),
+];
+
+/// A request to go to the logs at a time.
+pub struct Goto {
+    pub time: NaiveDateTime,
+    /// ID that the next line sent will have, if newer logs have to be skipped to get there
+    pub next_id: isize,
+    /// Number of lines after the one at the time to send too, so that they fill the screen
+    pub after: usize,
+    pub reply: Sender<Result<Jump, String>>,
+}
+
+/// Where the line at the time of a request is.
+pub enum Jump {
+    /// The line was already sent, and has this ID.
+    Sent(isize),
+    /// The lines between it and the lines already sent were skipped. The lines sent next start
+    /// with a notice, and the line has this ID.
+    Skipped(isize),
+}
+
+/// A log file that the history is read from.
+struct Source {
+    path: PathBuf,
+    /// The file and the date of its logs, which the logs sent are tagged with
+    file: Arc<LogFile>,
+}
+
+/// Lines of a source that were sent one after another.
+struct Segment {
+    source: usize,
+    /// Position in the file of the start of the oldest line sent
+    start: u64,
+    /// Position in the file of the end of the newest line
+    end: u64,
+    /// ID of the newest line, once it is sent
+    last_id: Option<isize>,
+    /// ID of the oldest line that the output thread got
+    min_id: isize,
+}
+
+/// Position of the line at a time.
+struct Location {
+    source: usize,
+    file: File,
+    /// Position in the file of the start of the line
+    start: u64,
+    /// Position in the file of the end of the last line after it that is sent too
+    end: u64,
+    /// Number of lines after it that are sent too
+    after: isize,
+}
+
+/// Read the logs in `latest` and then the archived logs in `archive_dir` that match `archives`,
+/// from the newest to the oldest, sending each line when the output thread asks for more.
+/// Compressed archives are decompressed into files in `spill_dir` to be read. `None` is sent
+/// after the oldest line, and requests to go to a time are answered until the thread is told to
+/// quit. Logs that do not exist yet are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn start(
+    latest: PathBuf,
+    archive_dir: PathBuf,
+    archives: ArchivePatterns,
+    spill_dir: PathBuf,
+    formats: LogFormats,
+    input: Receiver<()>,
+    requests: Receiver<Goto>,
+    output: Sender<Option<Line>>,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        // A server that is run as a child process may not have written any logs yet.
+        let archives = match archives.find(&archive_dir, &latest) {
+            Ok(archives) => archives,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let meta = match fs::metadata(&latest) {
+            Ok(meta) => Some(meta),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut sources = Vec::new();
+        if let Some(meta) = meta {
+            // The latest logs are from the day that they were last written.
+            let date = meta
+                .modified()
+                .ok()
+                .map(|t| DateTime::<Local>::from(t).date_naive());
+            sources.push(Source::new(latest, date));
+        }
+        for archive in archives.into_iter().rev() {
+            sources.push(Source::new(archive.path, archive.date));
+        }
+        let mut history = History {
+            sources,
+            formats,
+            segments: Vec::new(),
+            cursor: None,
+            next_id: -1,
+            spill_dir,
+            spills: Vec::new(),
+        };
+        if !history.sources.is_empty() {
+            let file = history.open(0)?;
+            history.set_cursor(0, ReverseLines::new(file)?);
+        }
+        history.run(&input, &requests, output)
+    })
+}
+
+/// Build the state of the server from all of the lines in `latest`, and send it once the end of
+/// the file is reached. This is apart from the history so that the history can be shown while the
+/// file is read. Nothing is sent if the file does not exist.
+pub fn scan(
+    latest: PathBuf,
+    formats: LogFormats,
+    input: Receiver<()>,
+    output: Sender<ServerState>,
+) -> JoinHandle<io::Result<()>> {
     thread::spawn(move || {
-        lazy_static! {
-            static ref LOG_REGEX: Regex = Regex::new(r"^\d{4}-\d\d-\d\d-\d+\.log\.gz$").unwrap();
-        }
-        let mut filenames = Vec::new();
-        for ent in fs::read_dir("logs")? {
-            let ent = ent?;
-            if let Some(s) = ent.file_name().to_str() {
-                if LOG_REGEX.is_match(s) {
-                    filenames.push(ent.path());
+        let file = match File::open(&latest) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut result = Ok(());
+        let lines = BufReader::new(file).split(b'\n').map_while(|line| {
+            if let Err(TryRecvError::Disconnected) = input.try_recv() {
+                return None;
+            }
+            line.map(to_string).map_err(|e| result = Err(e)).ok()
+        });
+        let mut state = ServerState::new();
+        state.seed(lines, &formats);
+        result?;
+        send(&input, &output, state);
+        Ok(())
+    })
+}
+
+impl Source {
+    fn new(path: PathBuf, date: Option<NaiveDate>) -> Source {
+        let file = Arc::new(LogFile {
+            path: Some(path.to_string_lossy().into_owned()),
+            date,
+        });
+        Source { path, file }
+    }
+}
+
+/// State of the history thread.
+struct History {
+    /// The log files from the newest to the oldest
+    sources: Vec<Source>,
+    formats: LogFormats,
+    /// The lines that were sent, which the last one is being added to
+    segments: Vec<Segment>,
+    /// Index of the source being read, and its lines that are left
+    cursor: Option<(usize, ReverseLines<File>)>,
+    /// ID of the next line sent, counting down from the newest line of the history
+    next_id: isize,
+    spill_dir: PathBuf,
+    /// The sources that were decompressed, and the files that they were decompressed into, from
+    /// the least recently used one to the most recently used one
+    spills: Vec<(usize, PathBuf)>,
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        for (_, spill) in &self.spills {
+            let _ = fs::remove_file(spill);
+        }
+    }
+}
+
+impl History {
+    /// Send the lines of the sources, answering requests to go to a time in the meantime. Once
+    /// all of the lines are sent, `None` is sent, and the requests are still answered.
+    fn run(
+        &mut self,
+        input: &Receiver<()>,
+        requests: &Receiver<Goto>,
+        output: Sender<Option<Line>>,
+    ) -> io::Result<()> {
+        loop {
+            let (line, start) = match self.next_line()? {
+                Some((line, start)) => (Some(line), start),
+                None => (None, None),
+            };
+            let end = line.is_none();
+            let mut skipped = false;
+            loop {
+                select! {
+                    recv(input) -> msg => if msg.is_err() { return Ok(()) },
+                    recv(requests) -> req => match req {
+                        Ok(req) => if self.goto(req, &output) {
+                            skipped = true;
+                            break;
+                        },
+                        Err(_) => return Ok(()),
+                    },
+                    send(output, line) -> _ => break,
+                }
+            }
+            if skipped {
+                continue;
+            }
+            if !end {
+                self.sent(start);
+                continue;
+            }
+            // Wait until the history skips to logs that were not sent.
+            loop {
+                select! {
+                    recv(input) -> msg => if msg.is_err() { return Ok(()) },
+                    recv(requests) -> req => match req {
+                        Ok(req) => if self.goto(req, &output) {
+                            break;
+                        },
+                        Err(_) => return Ok(()),
+                    },
                 }
             }
         }
-        filenames.sort_unstable();
+    }
+
+    /// Read the next line to send, and the position in its source where it starts, which is
+    /// `None` for the header after the lines of a source.
+    fn next_line(&mut self) -> io::Result<Option<(Line, Option<u64>)>> {
+        let (source, lines) = match &mut self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        if let Some(line) = lines.next() {
+            let file = self.sources[*source].file.clone();
+            let log = Line::Log(line?.replace('\t', "    "), file);
+            return Ok(Some((log, Some(lines.start()))));
+        }
+        let source = *source;
+        let log = Line::Header(self.sources[source].path.to_string_lossy().into_owned());
+        self.cursor = None;
+        if source + 1 < self.sources.len() {
+            let file = self.open(source + 1)?;
+            self.set_cursor(source + 1, ReverseLines::new(file)?);
+        }
+        Ok(Some((log, None)))
+    }
+
+    /// Keep track of a line that was sent, which starts at `start` in its source.
+    fn sent(&mut self, start: Option<u64>) {
+        if let Some(start) = start {
+            let segment = self.segments.last_mut().unwrap();
+            segment.start = start;
+            segment.last_id.get_or_insert(self.next_id);
+            segment.min_id = self.next_id;
+        }
+        self.next_id -= 1;
+    }
+
+    /// Start reading the lines of a source.
+    fn set_cursor(&mut self, source: usize, lines: ReverseLines<File>) {
+        self.segments.push(Segment {
+            source,
+            start: lines.start(),
+            end: lines.start(),
+            last_id: None,
+            min_id: 0,
+        });
+        self.cursor = Some((source, lines));
+    }
+
+    /// Open a source, decompressing it if it is compressed and was not decompressed recently.
+    fn open(&mut self, source: usize) -> io::Result<File> {
+        let path = &self.sources[source].path;
+        let decompressor = match decompressor(path) {
+            Some(decompressor) => decompressor,
+            None => return File::open(path),
+        };
+        if let Some(i) = self.spills.iter().position(|&(s, _)| s == source) {
+            let spill = self.spills.remove(i);
+            let file = File::open(&spill.1);
+            self.spills.push(spill);
+            return file;
+        }
+        if self.spills.len() >= MAX_SPILLS {
+            // Files that are still open can be read until they are closed.
+            let _ = fs::remove_file(self.spills.remove(0).1);
+        }
+        let (spill, mut file) = create_temp(&self.spill_dir, "log")?;
+        if let Err(e) = decompress(path, decompressor, &mut file) {
+            let _ = fs::remove_file(&spill);
+            return Err(e);
+        }
+        self.spills.push((source, spill));
+        Ok(file)
+    }
 
-        let latest = File::open("logs/latest.log")?;
-        let reader = BufReader::new(latest);
-        let lines = reader.lines().collect::<io::Result<Vec<_>>>();
-        let lines = lines?;
-        for line in lines.into_iter().rev() {
-            let log = Line::Log(line.replace('\t', "    "));
-            if send(&input, &output, log) {
-                return Ok(());
+    /// Answer a request to go to a time. If the line at the time was not sent yet, the lines
+    /// sent next start from there instead. Returns whether the line that was being sent was
+    /// skipped.
+    fn goto(&mut self, req: Goto, output: &Sender<Option<Line>>) -> bool {
+        let loc = match self.locate(req.time, req.after as isize) {
+            Ok(Some(loc)) => loc,
+            Ok(None) => {
+                let _ = req
+                    .reply
+                    .send(Err("there are no logs at that time".to_string()));
+                return false;
+            }
+            Err(e) => {
+                let _ = req
+                    .reply
+                    .send(Err(format!("failed to read old logs: {}", e)));
+                return false;
+            }
+        };
+        match self.sent_id(&loc) {
+            Ok(Some(id)) => {
+                let _ = req.reply.send(Ok(Jump::Sent(id)));
+                return false;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = req
+                    .reply
+                    .send(Err(format!("failed to read old logs: {}", e)));
+                return false;
             }
         }
-        let log = Line::Header("logs/latest.log".to_string());
-        if send(&input, &output, log) {
-            return Ok(());
+
+        // The lines that the output thread did not get yet are thrown away.
+        for segment in &mut self.segments {
+            segment.min_id = segment.min_id.max(req.next_id + 1);
+        }
+        self.next_id = req.next_id;
+        let id = req.next_id - 1 - loc.after;
+        if req.reply.send(Ok(Jump::Skipped(id))).is_err() {
+            return false;
+        }
+        self.set_cursor(loc.source, ReverseLines::with_end(loc.file, loc.end));
+        let time = req.time.format("%Y-%m-%d %H:%M:%S");
+        // The output thread takes the lines up to this one out of the channel.
+        let notice = format!("skipped newer logs to go to {}", time);
+        let _ = output.send(Some(Line::Notice(notice)));
+        self.next_id -= 1;
+        true
+    }
+
+    /// Find the first line at or after `time` and the `after` lines after it. The source is
+    /// picked by the date in its name, and then searched for the time.
+    fn locate(&mut self, time: NaiveDateTime, after: isize) -> io::Result<Option<Location>> {
+        if self.sources.is_empty() {
+            return Ok(None);
+        }
+        // The newest source that starts at or before the time
+        let mut found = None;
+        for (i, source) in self.sources.iter().enumerate() {
+            let starts_before = match source.file.date {
+                Some(date) if date > time.date() => false,
+                Some(date) if date < time.date() => true,
+                _ => matches!(self.first_time(i)?, Some(start) if start <= time),
+            };
+            if starts_before {
+                found = Some(i);
+                break;
+            }
         }
 
-        for filename in filenames.into_iter().rev() {
-            let raw_file = File::open(&filename)?;
-            let decoder = GzDecoder::new(raw_file);
-            let reader = BufReader::new(decoder);
-            let lines = reader.lines().collect::<io::Result<Vec<_>>>();
-            let lines = lines?;
-            for line in lines.into_iter().rev() {
-                let log = Line::Log(line.replace('\t', "    "));
-                if send(&input, &output, log) {
-                    return Ok(());
+        let (source, file, start) = match found {
+            Some(i) => {
+                let file = self.open(i)?;
+                let date = self.sources[i].file.date.unwrap_or_else(|| time.date());
+                match self.find_time(&file, date, time)? {
+                    Some(start) => (i, file, start),
+                    // The time is after the last line, so go to the first line of the next
+                    // source.
+                    None if i > 0 => (i - 1, self.open(i - 1)?, 0),
+                    // Or to the last line of all.
+                    None => {
+                        let mut lines = ReverseLines::new(&file)?;
+                        let start = match lines.next() {
+                            Some(line) => line.map(|_| lines.start())?,
+                            None => return Ok(None),
+                        };
+                        (i, file, start)
+                    }
                 }
             }
-            let log = Line::Header(filename.to_string_lossy().into_owned());
-            if send(&input, &output, log) {
-                return Ok(());
+            // The time is before the first line of all.
+            None => {
+                let i = self.sources.len() - 1;
+                (i, self.open(i)?, 0)
+            }
+        };
+        Ok(
+            lines_after(&file, start, after)?.map(|(end, after)| Location {
+                source,
+                file,
+                start,
+                end,
+                after,
+            }),
+        )
+    }
+
+    /// Get the date and time of the first line of a source that has a time.
+    fn first_time(&self, source: usize) -> io::Result<Option<NaiveDateTime>> {
+        let date = match self.sources[source].file.date {
+            Some(date) => date,
+            None => return Ok(None),
+        };
+        let path = &self.sources[source].path;
+        let file = File::open(path)?;
+        // Only the start of a compressed archive is decompressed.
+        let reader = match decompressor(path) {
+            Some(decompressor) => decompressor(file)?,
+            None => Box::new(file),
+        };
+        for line in BufReader::new(reader).split(b'\n').take(FIRST_TIME_LINES) {
+            if let Some(clock) = goto::clock(&to_string(line?), &self.formats) {
+                return Ok(Some(date.and_time(clock)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find the position of the first line of a file at or after `time`, where the file starts
+    /// at `date`.
+    fn find_time(
+        &self,
+        file: &File,
+        date: NaiveDate,
+        time: NaiveDateTime,
+    ) -> io::Result<Option<u64>> {
+        let mut file = file;
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        let mut dater = Dater::new(date, None, false);
+        let mut pos = 0;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            let line = String::from_utf8_lossy(&buf);
+            if let Some(clock) = goto::clock(line.trim_end(), &self.formats) {
+                if dater.next(clock) >= time {
+                    return Ok(Some(pos));
+                }
             }
+            pos += n as u64;
         }
+    }
 
-        Ok(())
-    })
+    /// Get the ID of the line at a location if it was already sent.
+    fn sent_id(&self, loc: &Location) -> io::Result<Option<isize>> {
+        for segment in &self.segments {
+            let last_id = match segment.last_id {
+                Some(id) if segment.source == loc.source => id,
+                _ => continue,
+            };
+            if loc.start < segment.start || loc.start > segment.end {
+                continue;
+            }
+            // Count the lines after it.
+            let mut file = &loc.file;
+            file.seek(SeekFrom::Start(loc.start))?;
+            let mut newlines = 0;
+            for byte in BufReader::new(file)
+                .bytes()
+                .take((segment.end - loc.start) as usize)
+            {
+                if byte? == b'\n' {
+                    newlines += 1;
+                }
+            }
+            let id = last_id - newlines;
+            if id >= segment.min_id {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Find the end of the line of a file that starts at `start` and the `after` lines after it, and
+/// how many lines there are after it, or `None` if there is no line there.
+fn lines_after(file: &File, start: u64, after: isize) -> io::Result<Option<(u64, isize)>> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut pos = start;
+    let mut end = None;
+    let mut count = -1;
+    while count < after {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let len = if buf.last() == Some(&b'\n') { n - 1 } else { n };
+        end = Some(pos + len as u64);
+        pos += n as u64;
+        count += 1;
+    }
+    Ok(end.map(|end| (end, count)))
+}
+
+/// Get the decompressor of an archive by the extension of its name, or `None` if it is not
+/// compressed.
+fn decompressor(path: &Path) -> Option<Decompressor> {
+    let ext = path.extension()?.to_str()?;
+    DECOMPRESSORS
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map(|&(_, decompressor)| decompressor)
+}
+
+/// Decompress an archive into the file `spill`, so that it can be read backwards without keeping
+/// all of it in memory.
+fn decompress(path: &Path, decompressor: Decompressor, spill: &mut File) -> io::Result<()> {
+    io::copy(&mut decompressor(File::open(path)?)?, spill)?;
+    spill.seek(SeekFrom::Start(0))?;
+    Ok(())
 }
 
-fn send(input: &Receiver<()>, output: &Sender<Line>, line: Line) -> bool {
+/// Create a new file in `dir` with the extension `ext` for data that is only needed while running.
+/// The directory may be shared with others, so a file that is already there, or a link, is never
+/// opened; another name is tried instead.
+pub fn create_temp(dir: &Path, ext: &str) -> io::Result<(PathBuf, File)> {
+    let mut tries = 0;
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let name = format!("better-console-{}-{:08x}.{}", process::id(), nanos, ext);
+        let path = dir.join(name);
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists && tries < MAX_TEMP_TRIES => {
+                tries += 1
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Iterator over the lines of a file from the last one to the first one, which reads the file
+/// backwards a block at a time.
+struct ReverseLines<R> {
+    reader: R,
+    /// Position in the file of the start of `buf`
+    pos: u64,
+    /// The part of the file before the lines that have been returned, from `pos`. Only its
+    /// first line can be incomplete.
+    buf: Vec<u8>,
+    /// Whether the first line of the file has been returned.
+    done: bool,
+    /// Position in the file of the start of the last line returned
+    start: u64,
+}
+
+impl<R: Read + Seek> ReverseLines<R> {
+    fn new(mut reader: R) -> io::Result<ReverseLines<R>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let mut end = len;
+        if len > 0 {
+            // A newline at the end of the file does not start another line.
+            let mut last = [0];
+            reader.seek(SeekFrom::Start(end - 1))?;
+            reader.read_exact(&mut last)?;
+            if last[0] == b'\n' {
+                end -= 1;
+            }
+        }
+        let mut lines = ReverseLines::with_end(reader, end);
+        lines.done = len == 0;
+        Ok(lines)
+    }
+
+    /// Read the lines before `end`, which is the end of a line.
+    fn with_end(reader: R, end: u64) -> ReverseLines<R> {
+        ReverseLines {
+            reader,
+            pos: end,
+            buf: Vec::new(),
+            done: false,
+            start: end,
+        }
+    }
+
+    /// Get the position in the file of the start of the last line returned, or the end of the
+    /// lines if none was returned yet.
+    fn start(&self) -> u64 {
+        self.start
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReverseLines<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(i) = self.buf.iter().rposition(|&b| b == b'\n') {
+                let line = self.buf.split_off(i + 1);
+                self.buf.pop();
+                self.start = self.pos + i as u64 + 1;
+                return Some(Ok(to_string(line)));
+            }
+            if self.pos == 0 {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                self.start = 0;
+                return Some(Ok(to_string(mem::take(&mut self.buf))));
+            }
+
+            // Read the block before `buf`.
+            let len = self.pos.min(BLOCK_SIZE);
+            self.pos -= len;
+            let mut block = vec![0; len as usize];
+            let read = self
+                .reader
+                .seek(SeekFrom::Start(self.pos))
+                .and_then(|_| self.reader.read_exact(&mut block));
+            if let Err(e) = read {
+                self.done = true;
+                self.pos = 0;
+                self.buf.clear();
+                return Some(Err(e));
+            }
+            block.append(&mut self.buf);
+            self.buf = block;
+        }
+    }
+}
+
+/// Convert a line read from a file to a string, without the line ending.
+fn to_string(mut line: Vec<u8>) -> String {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match String::from_utf8(line) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+fn send<T>(input: &Receiver<()>, output: &Sender<T>, item: T) -> bool {
     loop {
         select! {
             recv(input) -> msg => if msg.is_err() { return true },
-            send(output, line) -> _ => return false,
+            send(output, item) -> _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::{Cursor, Write};
+
+    use crossbeam::channel;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn reverse(data: &[u8]) -> Vec<String> {
+        ReverseLines::new(Cursor::new(data))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn lines() {
+        assert!(reverse(b"").is_empty());
+        assert_eq!(reverse(b"\n"), vec![""]);
+        assert_eq!(reverse(b"a\nb\n"), vec!["b", "a"]);
+        assert_eq!(reverse(b"a\nb"), vec!["b", "a"]);
+        assert_eq!(reverse(b"a\n\nb\n\n"), vec!["", "b", "", "a"]);
+    }
+
+    #[test]
+    fn crlf() {
+        assert_eq!(reverse(b"a\r\nb\r\n"), vec!["b", "a"]);
+        assert_eq!(reverse(b"a\rb\r\n\r\n"), vec!["", "a\rb"]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(reverse(b"ok\n\xffno\n"), vec!["\u{fffd}no", "ok"]);
+    }
+
+    #[test]
+    fn across_blocks() {
+        let mut data = Vec::new();
+        let mut lines = Vec::new();
+        // Lines of many lengths, so that lines and CRLF line endings are split between blocks.
+        for i in 0..5000 {
+            let line = "x".repeat(i * 7 % 300);
+            data.extend_from_slice(line.as_bytes());
+            data.extend_from_slice(if i % 3 == 0 { b"\r\n" } else { b"\n" });
+            lines.push(line);
         }
+        assert!(data.len() as u64 > 3 * BLOCK_SIZE);
+        lines.reverse();
+        assert_eq!(reverse(&data), lines);
+    }
+
+    #[test]
+    fn line_longer_than_block() {
+        let long = "y".repeat(BLOCK_SIZE as usize * 2 + 10);
+        let data = format!("a\n{}\nb\n", long);
+        assert_eq!(reverse(data.as_bytes()), vec!["b", &long, "a"]);
+    }
+
+    #[test]
+    fn starts() {
+        let mut lines = ReverseLines::new(Cursor::new(b"ab\ncd\nef\n")).unwrap();
+        assert_eq!(lines.start(), 8);
+        let mut starts = Vec::new();
+        while let Some(line) = lines.next() {
+            starts.push((line.unwrap(), lines.start()));
+        }
+        let expected = [("ef", 6), ("cd", 3), ("ab", 0)];
+        let expected: Vec<_> = expected.iter().map(|&(s, i)| (s.to_string(), i)).collect();
+        assert_eq!(starts, expected);
+    }
+
+    #[test]
+    fn with_end() {
+        let lines = ReverseLines::with_end(Cursor::new(b"ab\ncd\nef\n"), 5);
+        let lines: Vec<_> = lines.map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["cd", "ab"]);
+    }
+
+    #[test]
+    fn spills_are_reused() {
+        let dir = env::temp_dir().join(format!("better-console-test-{}", process::id()));
+        let spill_dir = dir.join("spill");
+        fs::create_dir_all(&spill_dir).unwrap();
+        let mut sources = Vec::new();
+        for i in 0..=MAX_SPILLS {
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            writeln!(gz, "line {}", i).unwrap();
+            let path = dir.join(format!("2024-01-0{}-1.log.gz", i + 1));
+            fs::write(&path, gz.finish().unwrap()).unwrap();
+            sources.push(Source::new(path, None));
+        }
+        let mut history = History {
+            sources,
+            formats: LogFormats::default(),
+            segments: Vec::new(),
+            cursor: None,
+            next_id: -1,
+            spill_dir: spill_dir.clone(),
+            spills: Vec::new(),
+        };
+        let spills = || fs::read_dir(&spill_dir).unwrap().count();
+
+        let mut text = String::new();
+        history.open(0).unwrap().read_to_string(&mut text).unwrap();
+        history.open(0).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "line 0\nline 0\n");
+        assert_eq!(spills(), 1);
+        for i in 1..=MAX_SPILLS {
+            history.open(i).unwrap();
+        }
+        assert_eq!(spills(), MAX_SPILLS);
+        drop(history);
+        assert_eq!(spills(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn temp_files_are_new() {
+        let dir = env::temp_dir().join(format!("better-console-test-{}-temp", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (path1, mut file1) = create_temp(&dir, "log").unwrap();
+        let (path2, _) = create_temp(&dir, "log").unwrap();
+        assert_ne!(path1, path2);
+        assert_eq!(path1.extension().unwrap(), "log");
+        file1.write_all(b"data").unwrap();
+        assert_eq!(fs::read(&path1).unwrap(), b"data");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_logs() {
+        let dir = env::temp_dir().join(format!("better-console-test-{}-missing", process::id()));
+        let (send_q, recv_q) = channel::bounded(0);
+        let (send_g, recv_g) = channel::bounded(0);
+        let (send, recv) = channel::bounded(16);
+        let history = start(
+            dir.join("latest.log"),
+            dir.clone(),
+            ArchivePatterns::default(),
+            dir.clone(),
+            LogFormats::default(),
+            recv_q,
+            recv_g,
+            send,
+        );
+        assert_eq!(recv.recv().unwrap(), None);
+        let (reply, answer) = channel::bounded(1);
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        send_g
+            .send(Goto {
+                time: date.and_hms_opt(0, 0, 0).unwrap(),
+                next_id: -1,
+                after: 0,
+                reply,
+            })
+            .unwrap();
+        assert!(answer.recv().unwrap().is_err());
+        drop(send_q);
+        history.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn scans_latest() {
+        let dir = env::temp_dir().join(format!("better-console-test-{}-scan", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let latest = dir.join("latest.log");
+        fs::write(
+            &latest,
+            "[09:59:00] [Server thread/INFO]: Starting minecraft server version 1.20.4\n\
+             [10:00:00] [Server thread/INFO]: Steve joined the game\r\n\
+             [10:01:00] [Server thread/INFO]: Alex joined the game\n\
+             [10:02:00] [Server thread/INFO]: Steve left the game\n",
+        )
+        .unwrap();
+        let (send_q, recv_q) = channel::bounded(0);
+        let (send, recv) = channel::bounded(1);
+        let scan = super::scan(latest, LogFormats::default(), recv_q, send);
+        let state = recv.recv().unwrap();
+        let players: Vec<_> = state.players().unwrap().keys().collect();
+        assert_eq!(players, ["Alex"]);
+        drop(send_q);
+        scan.join().unwrap().unwrap();
+
+        // Nothing is sent without a file.
+        let (send_q, recv_q) = channel::bounded(0);
+        let (send, recv) = channel::bounded(1);
+        let scan = super::scan(dir.join("none.log"), LogFormats::default(), recv_q, send);
+        assert!(recv.recv().is_err());
+        drop(send_q);
+        scan.join().unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
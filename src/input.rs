@@ -1,20 +1,54 @@
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 use crossbeam::channel::{Receiver, Sender};
-use rustbox::{Event, RustBox};
+use rustbox::{Event, Key, RustBox};
 
-pub fn start(rb: Arc<RustBox>, input: Receiver<()>, output: Sender<Event>) -> JoinHandle<()> {
+/// How long to wait for another key after Esc before deciding that Esc was pressed by itself.
+/// Terminals send Alt+key as Esc followed by the key.
+const ESC_TIMEOUT: Duration = Duration::from_millis(25);
+
+/// Input from the user.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Input {
+    Key(Key),
+    /// A key pressed while holding Alt.
+    Alt(Key),
+    Resize(i32, i32),
+}
+
+pub fn start(rb: Arc<RustBox>, input: Receiver<()>, output: Sender<Input>) -> JoinHandle<()> {
     thread::spawn(move || {
         let rb = rb;
         loop {
-            let event = rb.poll_event(false);
-            output.send(event.unwrap()).unwrap();
+            let mut events = Vec::new();
+            match rb.poll_event(false).unwrap() {
+                Event::KeyEvent(Key::Esc) => match rb.peek_event(ESC_TIMEOUT, false).unwrap() {
+                    Event::KeyEvent(key) => events.push(Input::Alt(key)),
+                    event => {
+                        events.push(Input::Key(Key::Esc));
+                        events.extend(convert(event));
+                    }
+                },
+                event => events.extend(convert(event)),
+            }
 
-            if input.recv().is_err() {
-                break;
+            for event in events {
+                output.send(event).unwrap();
+                if input.recv().is_err() {
+                    return;
+                }
             }
         }
     })
 }
+
+fn convert(event: Event) -> Option<Input> {
+    match event {
+        Event::KeyEvent(key) => Some(Input::Key(key)),
+        Event::ResizeEvent(w, h) => Some(Input::Resize(w, h)),
+        _ => None,
+    }
+}
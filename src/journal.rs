@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::history;
+use crate::output::{Line, LogFile};
+
+/// Number of lines between the lines of a run whose positions in the file are kept, so that
+/// reading a line only has to skip over the lines before it since the last of them.
+const INDEX_STEP: isize = 128;
+
+/// Lines with consecutive IDs that are written one after another in the file.
+#[derive(Default)]
+struct Run {
+    len: isize,
+    /// Positions in the file of the first line and of every `INDEX_STEP`th line after it
+    offsets: Vec<u64>,
+}
+
+/// Lines that have been moved out of memory, kept in a temporary file so that they can be read
+/// again. Lines are never changed, so each one is only written once.
+pub struct Journal {
+    /// Directory that the file is created in
+    dir: PathBuf,
+    file: Option<File>,
+    /// The runs in the file by the ID of their first line
+    runs: BTreeMap<isize, Run>,
+    /// ID of the first line of the run at the end of the file, which can be extended
+    last: Option<isize>,
+    /// Size of the file
+    size: u64,
+    /// The files of the logs that have been written, which logs refer to by index
+    files: Vec<Arc<LogFile>>,
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf) -> Journal {
+        Journal {
+            dir,
+            file: None,
+            runs: BTreeMap::new(),
+            last: None,
+            size: 0,
+            files: Vec::new(),
+        }
+    }
+
+    /// Get the ID of the oldest line that has been written.
+    pub fn first(&self) -> Option<isize> {
+        self.runs.keys().next().copied()
+    }
+
+    /// Check whether the line with the given ID has been written.
+    pub fn contains(&self, id: isize) -> bool {
+        matches!(self.runs.range(..=id).next_back(), Some((start, run)) if id < start + run.len)
+    }
+
+    /// Find the first ID of the lines before `end` that have all been written, going back at
+    /// most `max` lines.
+    pub fn written_before(&self, end: isize, max: isize) -> isize {
+        let mut start = end;
+        while start > end - max {
+            match self.runs.range(..start).next_back() {
+                Some((&first, run)) if first + run.len >= start => start = first.max(end - max),
+                _ => break,
+            }
+        }
+        start
+    }
+
+    /// Find the end of the IDs of the lines from `start` on that have all been written, going
+    /// forward at most `max` lines.
+    pub fn written_after(&self, start: isize, max: isize) -> isize {
+        let mut end = start;
+        while end < start + max {
+            match self.runs.range(..=end).next_back() {
+                Some((&first, run)) if first + run.len > end => {
+                    end = (first + run.len).min(start + max)
+                }
+                _ => break,
+            }
+        }
+        end
+    }
+
+    /// Write the lines with the IDs from `first` on, except the ones that are already written.
+    pub fn write<'a>(
+        &mut self,
+        first: isize,
+        lines: impl IntoIterator<Item = &'a Line>,
+    ) -> io::Result<()> {
+        let mut data = Vec::new();
+        for (id, line) in (first..).zip(lines) {
+            if self.contains(id) {
+                continue;
+            }
+            let extends = match self.last {
+                Some(last) => last + self.runs[&last].len == id,
+                None => false,
+            };
+            if !extends {
+                self.runs.insert(id, Run::default());
+                self.last = Some(id);
+            }
+            let offset = self.size + data.len() as u64;
+            let run = self.runs.get_mut(&self.last.unwrap()).unwrap();
+            if run.len % INDEX_STEP == 0 {
+                run.offsets.push(offset);
+            }
+            run.len += 1;
+            self.encode(line, &mut data);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        let file = self.file()?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Read the lines with the IDs in `ids`, which must all have been written.
+    pub fn read(&mut self, ids: Range<isize>) -> io::Result<Vec<Line>> {
+        let mut lines = Vec::new();
+        let mut id = ids.start;
+        while id < ids.end {
+            let (skip, offset, end) = match self.runs.range(..=id).next_back() {
+                Some((&first, run)) if id < first + run.len => {
+                    let index = (id - first) / INDEX_STEP;
+                    let skip = (id - first) % INDEX_STEP;
+                    (
+                        skip,
+                        run.offsets[index as usize],
+                        (first + run.len).min(ids.end),
+                    )
+                }
+                _ => return Err(io::Error::new(ErrorKind::NotFound, "line was not written")),
+            };
+            self.file()?.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(self.file.as_mut().unwrap());
+            for _ in 0..skip {
+                decode(&mut reader, &self.files)?;
+            }
+            for _ in id..end {
+                lines.push(decode(&mut reader, &self.files)?);
+            }
+            id = end;
+        }
+        Ok(lines)
+    }
+
+    /// Get the file, creating it the first time.
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            let (path, file) = history::create_temp(&self.dir, "lines")?;
+            // The file stays around until it is closed.
+            let _ = fs::remove_file(&path);
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Write a line as its kind, the index of its file if it is a log, the length of its text,
+    /// and its text.
+    fn encode(&mut self, line: &Line, data: &mut Vec<u8>) {
+        let (kind, text) = match line {
+            Line::Log(s, _) => (0, s),
+            Line::Header(s) => (1, s),
+            Line::Response(s) => (2, s),
+            Line::Notice(s) => (3, s),
+        };
+        data.push(kind);
+        if let Line::Log(_, file) = line {
+            // There are only a few files, and the logs of the newest ones are written most often.
+            let index = match self.files.iter().rposition(|f| Arc::ptr_eq(f, file)) {
+                Some(index) => index,
+                None => {
+                    self.files.push(file.clone());
+                    self.files.len() - 1
+                }
+            };
+            data.extend_from_slice(&(index as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        data.extend_from_slice(text.as_bytes());
+    }
+}
+
+fn decode(reader: &mut impl Read, files: &[Arc<LogFile>]) -> io::Result<Line> {
+    let mut kind = [0];
+    reader.read_exact(&mut kind)?;
+    let file = if kind[0] == 0 {
+        let index = read_u32(reader)? as usize;
+        let file = files.get(index);
+        Some(file.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "unknown log file"))?)
+    } else {
+        None
+    };
+    let len = read_u32(reader)?;
+    let mut text = vec![0; len as usize];
+    reader.read_exact(&mut text)?;
+    let text = String::from_utf8(text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Ok(match (kind[0], file) {
+        (0, Some(file)) => Line::Log(text, file.clone()),
+        (1, _) => Line::Header(text),
+        (2, _) => Line::Response(text),
+        _ => Line::Notice(text),
+    })
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn logs(ids: Range<isize>, file: &Arc<LogFile>) -> Vec<Line> {
+        ids.map(|id| Line::Log(format!("log {}", id), file.clone()))
+            .collect()
+    }
+
+    fn log_file(path: &str) -> Arc<LogFile> {
+        Arc::new(LogFile {
+            path: Some(path.to_string()),
+            date: None,
+        })
+    }
+
+    #[test]
+    fn kinds() {
+        let file = log_file("latest.log");
+        let lines = vec![
+            Line::Log("a log\twith a tab".to_string(), file.clone()),
+            Line::Header("logs/2024-01-01-1.log.gz".to_string()),
+            Line::Response("There are 0 of a max of 20 players online:".to_string()),
+            Line::Notice("server exited: exit status: 0 ☃".to_string()),
+            Line::Log(String::new(), file.clone()),
+        ];
+        let mut journal = Journal::new(env::temp_dir());
+        journal.write(-2, &lines).unwrap();
+        assert_eq!(journal.read(-2..3).unwrap(), lines);
+        assert_eq!(journal.read(0..2).unwrap(), lines[2..4]);
+    }
+
+    #[test]
+    fn log_files() {
+        let old = log_file("2024-01-01-1.log.gz");
+        let latest = log_file("latest.log");
+        let mut lines = logs(0..2, &old);
+        lines.extend(logs(2..4, &latest));
+        lines.extend(logs(4..5, &old));
+        let mut journal = Journal::new(env::temp_dir());
+        journal.write(0, &lines).unwrap();
+        let read = journal.read(0..5).unwrap();
+        assert_eq!(read, lines);
+        // The lines refer to the same files again.
+        for (line, file) in read.iter().zip(&[&old, &old, &latest, &latest, &old]) {
+            match line {
+                Line::Log(_, f) => assert!(Arc::ptr_eq(f, file)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn runs() {
+        let file = log_file("latest.log");
+        let mut journal = Journal::new(env::temp_dir());
+        assert_eq!(journal.first(), None);
+        journal.write(10, &logs(10..20, &file)).unwrap();
+        journal.write(-5, &logs(-5..0, &file)).unwrap();
+        // Extends the run at the end of the file.
+        journal.write(20, &logs(20..25, &file)).unwrap();
+        assert_eq!(journal.first(), Some(-5));
+        assert!(journal.contains(-5));
+        assert!(journal.contains(-1));
+        assert!(!journal.contains(0));
+        assert!(!journal.contains(9));
+        assert!(journal.contains(24));
+        assert!(!journal.contains(25));
+
+        assert_eq!(journal.written_before(25, 100), 10);
+        assert_eq!(journal.written_before(25, 4), 21);
+        assert_eq!(journal.written_before(0, 100), -5);
+        assert_eq!(journal.written_before(5, 100), 5);
+        assert_eq!(journal.written_after(10, 100), 25);
+        assert_eq!(journal.written_after(12, 4), 16);
+        assert_eq!(journal.written_after(0, 100), 0);
+
+        assert_eq!(journal.read(18..22).unwrap(), logs(18..22, &file));
+        assert_eq!(journal.read(0..1).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn adjacent_runs() {
+        let file = log_file("latest.log");
+        let mut journal = Journal::new(env::temp_dir());
+        journal.write(5, &logs(5..10, &file)).unwrap();
+        journal.write(0, &logs(0..5, &file)).unwrap();
+        assert_eq!(journal.written_before(10, 100), 0);
+        assert_eq!(journal.written_after(0, 100), 10);
+        assert_eq!(journal.read(3..7).unwrap(), logs(3..7, &file));
+    }
+
+    #[test]
+    fn written_lines_are_skipped() {
+        let file = log_file("latest.log");
+        let mut journal = Journal::new(env::temp_dir());
+        journal.write(0, &logs(0..10, &file)).unwrap();
+        let size = journal.size;
+        journal.write(0, &logs(0..10, &file)).unwrap();
+        assert_eq!(journal.size, size);
+        // Only the lines after the ones written are added, to the same run.
+        journal.write(5, &logs(5..15, &file)).unwrap();
+        assert_eq!(journal.runs.len(), 1);
+        assert_eq!(journal.read(0..15).unwrap(), logs(0..15, &file));
+    }
+
+    #[test]
+    fn long_runs() {
+        let file = log_file("latest.log");
+        let mut journal = Journal::new(env::temp_dir());
+        // Written a few lines at a time, like lines that are evicted while following new logs.
+        for start in (0..1000).step_by(7) {
+            journal
+                .write(start, &logs(start..(start + 7).min(1000), &file))
+                .unwrap();
+        }
+        assert_eq!(journal.runs.len(), 1);
+        for ids in [0..1, 127..129, 128..129, 300..812, 999..1000, 0..1000] {
+            assert_eq!(journal.read(ids.clone()).unwrap(), logs(ids, &file));
+        }
+    }
+}
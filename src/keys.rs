@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+
+use rustbox::Key;
+use serde::de;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::input::Input;
+
+/// Something that a key can be bound to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Search,
+    Filter,
+    /// Go to the logs at a date or time.
+    Goto,
+    /// Reverse search through the command history.
+    HistorySearch,
+    /// Go to the previous match of the search.
+    SearchOlder,
+    /// Go to the next match of the search.
+    SearchNewer,
+    /// Stop searching and remove the highlighting of matches, or leave the prompt of a filter or
+    /// a date. The history search is left with the command that was found.
+    StopSearch,
+    /// Leave a prompt without doing anything, or stop searching.
+    Cancel,
+    /// Switch the arrow keys between moving in the prompt and scrolling the logs.
+    ToggleFocus,
+    /// Switch between wrapping long lines and scrolling horizontally.
+    ToggleWrap,
+    /// Expand or collapse the stack traces and other groups of lines.
+    ToggleTraces,
+    /// Switch between showing repeated logs and collapsing them with a count.
+    ToggleRepeats,
+    /// Show or hide the panel listing the players online.
+    TogglePlayers,
+    /// Switch between showing all logs and only chat.
+    ToggleChat,
+    /// Scroll up, or recall the previous command when the prompt has focus.
+    ScrollUp,
+    /// Scroll down, or recall the next command when the prompt has focus.
+    ScrollDown,
+    /// Scroll left, unless the prompt has focus and is not empty.
+    ScrollLeft,
+    /// Scroll right, unless the prompt has focus and is not empty.
+    ScrollRight,
+    ScrollPageUp,
+    ScrollPageDown,
+    /// Scroll to the newest logs, unless the prompt has focus and is not empty.
+    ScrollEnd,
+    /// Recall the previous command.
+    HistoryOlder,
+    /// Recall the next command.
+    HistoryNewer,
+    /// Send the command in the prompt.
+    Submit,
+    /// Complete the word before the cursor.
+    Complete,
+}
+
+/// Names of the actions in the configuration file.
+const ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("search", Action::Search),
+    ("filter", Action::Filter),
+    ("goto", Action::Goto),
+    ("history-search", Action::HistorySearch),
+    ("search-older", Action::SearchOlder),
+    ("search-newer", Action::SearchNewer),
+    ("stop-search", Action::StopSearch),
+    ("cancel", Action::Cancel),
+    ("toggle-focus", Action::ToggleFocus),
+    ("toggle-wrap", Action::ToggleWrap),
+    ("toggle-traces", Action::ToggleTraces),
+    ("toggle-repeats", Action::ToggleRepeats),
+    ("toggle-players", Action::TogglePlayers),
+    ("toggle-chat", Action::ToggleChat),
+    ("scroll-up", Action::ScrollUp),
+    ("scroll-down", Action::ScrollDown),
+    ("scroll-left", Action::ScrollLeft),
+    ("scroll-right", Action::ScrollRight),
+    ("scroll-page-up", Action::ScrollPageUp),
+    ("scroll-page-down", Action::ScrollPageDown),
+    ("scroll-end", Action::ScrollEnd),
+    ("history-older", Action::HistoryOlder),
+    ("history-newer", Action::HistoryNewer),
+    ("submit", Action::Submit),
+    ("complete", Action::Complete),
+];
+
+/// Action name that removes a default binding.
+const UNBOUND: &str = "none";
+
+const DEFAULT_BINDINGS: &[(Input, Action)] = &[
+    (Input::Key(Key::Ctrl('q')), Action::Quit),
+    (Input::Key(Key::Ctrl('f')), Action::Search),
+    (Input::Key(Key::Ctrl('l')), Action::Filter),
+    (Input::Key(Key::Ctrl('g')), Action::Goto),
+    (Input::Key(Key::Ctrl('r')), Action::HistorySearch),
+    (Input::Key(Key::Ctrl('p')), Action::SearchOlder),
+    (Input::Key(Key::Ctrl('n')), Action::SearchNewer),
+    (Input::Key(Key::Esc), Action::StopSearch),
+    (Input::Key(Key::Ctrl('c')), Action::Cancel),
+    (Input::Key(Key::Ctrl('o')), Action::ToggleFocus),
+    (Input::Alt(Key::Char('w')), Action::ToggleWrap),
+    (Input::Alt(Key::Char('t')), Action::ToggleTraces),
+    (Input::Alt(Key::Char('r')), Action::ToggleRepeats),
+    (Input::Alt(Key::Char('p')), Action::TogglePlayers),
+    (Input::Alt(Key::Char('c')), Action::ToggleChat),
+    (Input::Key(Key::Up), Action::ScrollUp),
+    (Input::Key(Key::Down), Action::ScrollDown),
+    (Input::Key(Key::Left), Action::ScrollLeft),
+    (Input::Key(Key::Right), Action::ScrollRight),
+    (Input::Key(Key::PageUp), Action::ScrollPageUp),
+    (Input::Key(Key::PageDown), Action::ScrollPageDown),
+    (Input::Key(Key::End), Action::ScrollEnd),
+    (Input::Key(Key::Enter), Action::Submit),
+    (Input::Key(Key::Tab), Action::Complete),
+];
+
+/// Key bindings. The bindings in the configuration file are added to the default ones, replacing
+/// them when the same key is used.
+#[derive(Clone, Debug)]
+pub struct Keys {
+    bindings: HashMap<Input, Action>,
+}
+
+impl Keys {
+    /// Return the action bound to `input`, if any.
+    pub fn get(&self, input: Input) -> Option<Action> {
+        self.bindings.get(&input).copied()
+    }
+
+    /// Describe the keys bound to groups of actions, like `ctrl-Q = quit; PgUp/PgDn = scroll`.
+    /// Groups without any keys are left out.
+    pub fn hint(&self, hints: &[(&[Action], &str)]) -> String {
+        let mut parts = Vec::new();
+        for (actions, what) in hints {
+            let keys: Vec<_> = actions.iter().flat_map(|&a| self.keys(a)).collect();
+            if !keys.is_empty() {
+                parts.push(format!("{} = {}", keys.join("/"), what));
+            }
+        }
+        parts.join("; ")
+    }
+
+    /// Get the names of the keys bound to an action.
+    fn keys(&self, action: Action) -> Vec<String> {
+        let mut keys: Vec<_> = self
+            .bindings
+            .iter()
+            .filter(|&(_, &a)| a == action)
+            .map(|(&input, _)| chord_name(input))
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys {
+            bindings: DEFAULT_BINDINGS.iter().copied().collect(),
+        }
+    }
+}
+
+/// Parse a key chord such as `ctrl-q`, `alt-b` or `pgup`.
+fn parse_chord(chord: &str) -> Option<Input> {
+    let mut rest = chord.trim();
+    let mut ctrl = false;
+    let mut alt = false;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        if lower.starts_with("ctrl-") && rest.len() > 5 {
+            ctrl = true;
+            rest = &rest[5..];
+        } else if lower.starts_with("alt-") && rest.len() > 4 {
+            alt = true;
+            rest = &rest[4..];
+        } else {
+            break;
+        }
+    }
+
+    let key = if ctrl {
+        // Terminals send some control characters for other keys.
+        let mut chars = rest.chars();
+        match (chars.next()?.to_ascii_lowercase(), chars.next()) {
+            ('i', None) => Key::Tab,
+            ('m', None) => Key::Enter,
+            ('[', None) => Key::Esc,
+            (c @ 'a'..='z', None) | (c @ ('\\' | ']' | '6' | '/'), None) => Key::Ctrl(c),
+            _ => return None,
+        }
+    } else {
+        match rest.to_ascii_lowercase().as_str() {
+            "tab" => Key::Tab,
+            "enter" | "return" => Key::Enter,
+            "esc" | "escape" => Key::Esc,
+            "backspace" => Key::Backspace,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "delete" | "del" => Key::Delete,
+            "insert" | "ins" => Key::Insert,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pgup" | "pageup" => Key::PageUp,
+            "pgdn" | "pagedown" => Key::PageDown,
+            "space" => Key::Char(' '),
+            name => {
+                let mut chars = rest.chars();
+                match (chars.next()?, chars.next()) {
+                    (c, None) => Key::Char(c),
+                    _ => match name.strip_prefix('f').map(str::parse) {
+                        Some(Ok(n @ 1..=12)) => Key::F(n),
+                        _ => return None,
+                    },
+                }
+            }
+        }
+    };
+    Some(if alt {
+        Input::Alt(key)
+    } else {
+        Input::Key(key)
+    })
+}
+
+/// Get the name of a key chord to show, like `ctrl-Q` or `PgUp`.
+fn chord_name(input: Input) -> String {
+    let (alt, key) = match input {
+        Input::Key(key) => ("", key),
+        Input::Alt(key) => ("alt-", key),
+        Input::Resize(..) => return String::new(),
+    };
+    let name = match key {
+        Key::Tab => "Tab".to_string(),
+        Key::Enter => "Enter".to_string(),
+        Key::Esc => "Esc".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Delete => "Del".to_string(),
+        Key::Insert => "Ins".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PgUp".to_string(),
+        Key::PageDown => "PgDn".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c.to_ascii_uppercase()),
+        Key::F(n) => format!("F{}", n),
+        Key::Unknown(code) => format!("key {}", code),
+    };
+    format!("{}{}", alt, name)
+}
+
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+        impl<'a> Visitor<'a> for KeysVisitor {
+            type Value = Keys;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                fmt.write_str("a table of key bindings")
+            }
+
+            fn visit_map<A: MapAccess<'a>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut keys = Keys::default();
+                // The chords in the configuration, to find different ways of writing the same one
+                let mut chords: HashMap<Input, String> = HashMap::new();
+                while let Some((chord, name)) = map.next_entry::<String, String>()? {
+                    let input = match parse_chord(&chord) {
+                        Some(input) => input,
+                        None => return Err(de::Error::custom(format!("invalid key: '{}'", chord))),
+                    };
+                    if let Some(other) = chords.get(&input) {
+                        return Err(de::Error::custom(format!(
+                            "conflicting bindings for the same key: '{}' and '{}'",
+                            other, chord
+                        )));
+                    }
+                    chords.insert(input, chord);
+
+                    if name == UNBOUND {
+                        keys.bindings.remove(&input);
+                        continue;
+                    }
+                    match ACTIONS.iter().find(|(n, _)| *n == name) {
+                        Some(&(_, action)) => keys.bindings.insert(input, action),
+                        None => {
+                            return Err(de::Error::custom(format!("unknown action: '{}'", name)))
+                        }
+                    };
+                }
+                Ok(keys)
+            }
+        }
+        de.deserialize_map(KeysVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords() {
+        assert_eq!(parse_chord("ctrl-q"), Some(Input::Key(Key::Ctrl('q'))));
+        assert_eq!(parse_chord("Ctrl-Q"), Some(Input::Key(Key::Ctrl('q'))));
+        assert_eq!(parse_chord("alt-b"), Some(Input::Alt(Key::Char('b'))));
+        assert_eq!(parse_chord("alt-ctrl-x"), Some(Input::Alt(Key::Ctrl('x'))));
+        assert_eq!(parse_chord("ctrl-i"), Some(Input::Key(Key::Tab)));
+        assert_eq!(parse_chord("ctrl-["), Some(Input::Key(Key::Esc)));
+        assert_eq!(parse_chord("pgup"), Some(Input::Key(Key::PageUp)));
+        assert_eq!(parse_chord("F5"), Some(Input::Key(Key::F(5))));
+        assert_eq!(parse_chord("space"), Some(Input::Key(Key::Char(' '))));
+        assert_eq!(parse_chord("-"), Some(Input::Key(Key::Char('-'))));
+    }
+
+    #[test]
+    fn invalid_chords() {
+        assert_eq!(parse_chord("ctrl-"), None);
+        assert_eq!(parse_chord("ctrl-1"), None);
+        assert_eq!(parse_chord("f13"), None);
+        assert_eq!(parse_chord("hyper-a"), None);
+        assert_eq!(parse_chord(""), None);
+    }
+
+    #[test]
+    fn bindings() {
+        let keys: Keys = toml::from_str(
+            r#"
+            "ctrl-x" = "quit"
+            "ctrl-q" = "none"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(keys.get(Input::Key(Key::Ctrl('x'))), Some(Action::Quit));
+        assert_eq!(keys.get(Input::Key(Key::Ctrl('q'))), None);
+        assert_eq!(keys.get(Input::Key(Key::Up)), Some(Action::ScrollUp));
+    }
+
+    #[test]
+    fn hints() {
+        const HINTS: &[(&[Action], &str)] = &[
+            (&[Action::Quit], "quit"),
+            (&[Action::ScrollPageUp, Action::ScrollPageDown], "scroll"),
+            (&[Action::Search], "search"),
+            (&[Action::ToggleWrap], "wrap"),
+        ];
+        assert_eq!(
+            Keys::default().hint(HINTS),
+            "ctrl-Q = quit; PgUp/PgDn = scroll; ctrl-F = search; alt-w = wrap"
+        );
+        let keys: Keys = toml::from_str(
+            r#"
+            "ctrl-x" = "quit"
+            "ctrl-q" = "none"
+            "f3" = "search"
+            "pgup" = "none"
+            "pgdn" = "none"
+            "alt-space" = "toggle-wrap"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            keys.hint(HINTS),
+            "ctrl-X = quit; F3/ctrl-F = search; alt-Space/alt-w = wrap"
+        );
+    }
+
+    #[test]
+    fn binding_errors() {
+        let error = |text| toml::from_str::<Keys>(text).err().unwrap().to_string();
+        let conflict = error("\"ctrl-i\" = \"quit\"\n\"tab\" = \"submit\"");
+        assert!(conflict.contains("conflicting bindings for the same key: 'ctrl-i' and 'tab'"));
+        assert!(error("\"ctrl-1\" = \"quit\"").contains("invalid key: 'ctrl-1'"));
+        assert!(error("\"ctrl-x\" = \"fly\"").contains("unknown action: 'fly'"));
+    }
+}
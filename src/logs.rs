@@ -1,31 +1,207 @@
+use std::fs;
+use std::fs::{File, Metadata};
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::process::{Child, ChildStdout, Command, Stdio};
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
-use crossbeam::channel::Sender;
+use crossbeam::channel::{select, Receiver, Sender};
 
-use crate::output::Line;
+use crate::output::{Line, LogFile};
 
-pub fn spawn_tail() -> io::Result<Child> {
-    let mut cmd = Command::new("tail");
-    cmd.arg("-n0");
-    cmd.arg("-F");
-    cmd.arg("logs/latest.log");
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::null());
-    cmd.spawn()
+/// How long to wait before checking the log file again after reaching its end.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A log file that is currently being followed.
+struct Followed {
+    reader: BufReader<File>,
+    id: Option<FileId>,
+    pos: u64,
+}
+
+impl Followed {
+    fn open(path: &Path, at_end: bool) -> io::Result<Followed> {
+        let mut file = File::open(path)?;
+        let meta = file.metadata()?;
+        let pos = if at_end {
+            file.seek(SeekFrom::End(0))?
+        } else {
+            0
+        };
+        Ok(Followed {
+            reader: BufReader::new(file),
+            id: file_id(&meta),
+            pos,
+        })
+    }
 }
 
-pub fn start(stdout: ChildStdout, output: Sender<Line>) -> JoinHandle<io::Result<()>> {
+/// Follow the log file at `path`, sending each line that is appended to it. Like `tail -F`, this
+/// starts at the end of the file, and handles the file being truncated, rotated, or removed and
+/// recreated.
+pub fn start(
+    path: PathBuf,
+    input: Receiver<()>,
+    output: Sender<Line>,
+) -> JoinHandle<io::Result<()>> {
     thread::spawn(move || {
-        let stdout = BufReader::new(stdout);
+        let mut followed = match Followed::open(&path, true) {
+            Ok(f) => Some(f),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let mut file = LogFile::today(Some(path.to_string_lossy().into_owned()));
+        // Part of a line that has been read but not yet terminated by a newline.
+        let mut partial = Vec::new();
+        // Whether the file at `path` has been replaced by a new one.
+        let mut rotated = false;
 
-        for line in stdout.lines() {
-            output.send(Line::Log(line?.replace('\t', "    "))).unwrap();
-        }
+        loop {
+            if let Some(f) = &mut followed {
+                // The server may write anything, so invalid UTF-8 is replaced rather than ending the
+                // thread.
+                let n = f.reader.read_until(b'\n', &mut partial)?;
+                f.pos += n as u64;
+                if partial.ends_with(b"\n") {
+                    let line = String::from_utf8_lossy(&partial);
+                    let line = line.trim_end_matches(&['\r', '\n'][..]);
+                    LogFile::keep_today(&mut file);
+                    let log = Line::Log(line.replace('\t', "    "), file.clone());
+                    partial.clear();
+                    if send(&input, &output, log) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                if n != 0 {
+                    // Incomplete line; wait for the rest of it.
+                    continue;
+                }
+            }
 
-        Ok(())
+            if rotated {
+                // Everything that was written to the old file has been read, so switch to the new
+                // one and read it from the beginning.
+                rotated = false;
+                if !partial.is_empty() {
+                    LogFile::keep_today(&mut file);
+                    let line = String::from_utf8_lossy(&partial);
+                    let log = Line::Log(line.replace('\t', "    "), file.clone());
+                    partial.clear();
+                    if send(&input, &output, log) {
+                        return Ok(());
+                    }
+                }
+                followed = reopen(&path)?;
+                continue;
+            }
+
+            // We have reached the end of the file, so wait for something to happen.
+            select! {
+                recv(input) -> msg => if msg.is_err() { return Ok(()) },
+                default(POLL_INTERVAL) => (),
+            }
+
+            match fs::metadata(&path) {
+                Ok(meta) => match &mut followed {
+                    Some(f) if f.id.is_some() && f.id != file_id(&meta) => {
+                        // The file was rotated. Read what is left of the old file first.
+                        rotated = true;
+                    }
+                    Some(f) if meta.len() < f.pos => {
+                        // The file was truncated.
+                        partial.clear();
+                        f.pos = f.reader.seek(SeekFrom::Start(0))?;
+                    }
+                    Some(_) => (),
+                    None => followed = reopen(&path)?,
+                },
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                    // The file was removed, e.g. while the server is compressing it. Keep reading
+                    // what is left of the old file until the new one shows up.
+                }
+                Err(e) => return Err(e),
+            }
+        }
     })
 }
+
+/// Open the file at `path` from the start, or return `None` if it does not exist (yet).
+fn reopen(path: &Path) -> io::Result<Option<Followed>> {
+    match Followed::open(path, false) {
+        Ok(f) => Ok(Some(f)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Something that identifies a file independently of its path.
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<FileId> {
+    // Rotation is detected through truncation instead.
+    None
+}
+
+fn send(input: &Receiver<()>, output: &Sender<Line>, line: Line) -> bool {
+    loop {
+        select! {
+            recv(input) -> msg => if msg.is_err() { return true },
+            send(output, line) -> msg => return msg.is_err(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::channel;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    fn next_log(recv: &Receiver<Line>) -> String {
+        match recv.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Line::Log(log, _) => log,
+            line => panic!("unexpected line: {:?}", line),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let path =
+            env::temp_dir().join(format!("better-console-test-{}-follow.log", process::id()));
+        let mut writer = File::create(&path).unwrap();
+        let (send_q, recv_q) = channel::bounded(0);
+        let (send, recv) = channel::bounded(16);
+        let follower = start(path.clone(), recv_q, send);
+        // Give the follower time to open the file before anything is written.
+        thread::sleep(POLL_INTERVAL * 2);
+
+        writer.write_all(b"a\xffb\n").unwrap();
+        assert_eq!(next_log(&recv), "a\u{fffd}b");
+        // A character that is split between two writes.
+        writer.write_all(b"caf\xc3").unwrap();
+        writer.flush().unwrap();
+        thread::sleep(POLL_INTERVAL * 2);
+        writer.write_all(b"\xa9\r\n").unwrap();
+        assert_eq!(next_log(&recv), "café");
+
+        drop(send_q);
+        follower.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,23 +1,55 @@
+use std::env;
 use std::fs::File;
 use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::Arc;
 
 use crossbeam::channel;
 use rustbox::{InitOptions, OutputMode, RustBox};
 
+use crate::complete::Completer;
 use crate::config::Config;
+use crate::recall::CommandHistory;
 
+mod alert;
+mod archive;
+mod args;
+mod chat;
 mod command;
+mod complete;
 mod config;
+mod editor;
+mod filter;
+mod format;
+mod goto;
 mod history;
 mod input;
+mod journal;
+mod keys;
 mod logs;
 mod output;
+mod rcon;
+mod recall;
+mod server;
+mod status;
+mod style;
 
 fn main() {
+    let args = args::parse();
+    if let Some(dir) = &args.dir {
+        env::set_current_dir(dir).unwrap_or_else(|e| {
+            eprintln!("failed to change to {}: {}", dir.display(), e);
+            process::exit(2);
+        });
+    }
+
     // Load configuration:
-    let conf = match File::open("better-console.toml") {
+    let conf_path = match &args.config {
+        Some(path) => path.clone(),
+        None => PathBuf::from("better-console.toml"),
+    };
+    let mut conf = match File::open(&conf_path) {
         Ok(mut f) => {
             let mut s = String::new();
             f.read_to_string(&mut s).unwrap();
@@ -26,7 +58,7 @@ fn main() {
                 process::exit(2);
             })
         }
-        Err(ref e) if e.kind() == ErrorKind::NotFound => {
+        Err(ref e) if e.kind() == ErrorKind::NotFound && args.config.is_none() => {
             // File not found, so we'll use the default configuration.
             Config::default()
         }
@@ -35,8 +67,35 @@ fn main() {
             process::exit(2);
         }
     };
+    // Command-line arguments override the configuration file.
+    if let Some(path) = &args.log {
+        conf.log_file = path.to_string_lossy().into_owned();
+    }
+    if let Some(path) = &args.archive_dir {
+        conf.archive_dir = path.to_string_lossy().into_owned();
+    }
+    if let Some(path) = &args.command_fifo {
+        conf.transport.fifo = path.to_string_lossy().into_owned();
+    }
     let conf = Arc::new(conf);
 
+    // Load the command history:
+    let commands = CommandHistory::load(PathBuf::from(&conf.history_file), conf.history_size)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to read command history: {}", e);
+            process::exit(2);
+        });
+
+    // Load the command tree for completion:
+    let completer = match Completer::load(Path::new(&conf.commands_file)) {
+        Ok(completer) => completer,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Completer::new(),
+        Err(e) => {
+            eprintln!("failed to read {}: {}", conf.commands_file, e);
+            process::exit(2);
+        }
+    };
+
     // Initialize RustBox:
     let rb = RustBox::init(InitOptions {
         buffer_stderr: false,
@@ -54,39 +113,93 @@ fn main() {
     let (send_iq, recv_iq) = channel::bounded(0);
     // Quit signal for history thread
     let (send_hq, recv_hq) = channel::bounded(0);
+    // Quit signal for scan thread
+    let (send_sq, recv_sq) = channel::bounded(0);
+    // Quit signal for logs thread
+    let (send_lq, recv_lq) = channel::bounded(0);
 
-    // Logs thread -- sends new incoming logs to the output thread
-    let mut tail = logs::spawn_tail().unwrap_or_else(|e| {
-        eprintln!("failed to spawn tail: {}", e);
-        process::exit(2)
-    });
-    let (send_l, recv_l) = channel::bounded(16);
-    let logs = logs::start(tail.stdout.take().unwrap(), send_l);
+    // Whether the server is run as a child process
+    let managed = !conf.server.command.is_empty();
 
-    // History thread -- sends old logs to the output thread when requested
+    // History thread -- sends old logs to the output thread when requested. It also finds the
+    // logs at a time for the output thread.
     let (send_h, recv_h) = channel::bounded(16);
-    let history = history::start(recv_hq, send_h);
+    let (send_g, recv_g) = channel::bounded(0);
+    let history = history::start(
+        PathBuf::from(&conf.log_file),
+        PathBuf::from(&conf.archive_dir),
+        conf.archives.clone(),
+        conf.spill_dir(),
+        conf.log_formats.clone(),
+        recv_hq,
+        recv_g,
+        send_h,
+    );
+
+    // Scan thread -- sends the state of the server read from the whole of latest.log, if the
+    // output thread should scan it.
+    let (send_s, recv_s) = channel::bounded(1);
+    let scan = if conf.scan_latest_log {
+        Some(history::scan(
+            PathBuf::from(&conf.log_file),
+            conf.log_formats.clone(),
+            recv_sq,
+            send_s,
+        ))
+    } else {
+        drop(send_s);
+        None
+    };
+
+    // Logs thread -- sends new incoming logs to the output thread. If the server is run as a
+    // child process, its output is used instead.
+    let (send_l, recv_l) = channel::bounded(16);
+    let logs = if managed {
+        None
+    } else {
+        let path = PathBuf::from(&conf.log_file);
+        Some(logs::start(path, recv_lq, send_l.clone()))
+    };
 
     // Input thread -- forwards user input to the output thread
     let (send_i, recv_i) = channel::bounded(0);
     let input = input::start(rb.clone(), recv_iq, send_i);
 
-    // Command thread -- sends commands to the server when requested
+    // Command thread -- sends commands to the server when requested, or runs the server.
+    // Command responses and the output of the server are shown like new logs.
     let (send_c, recv_c) = channel::bounded(16);
-    let command = command::start(recv_c);
+    let command = if managed {
+        server::start(conf.server.clone(), recv_c, send_l)
+    } else {
+        command::start(conf.transport.clone(), recv_c, send_l)
+    };
 
     // Run the output ("main") thread.
-    output::run(conf, rb, recv_h, recv_l, recv_i, send_c, send_iq);
+    output::run(
+        conf, rb, recv_h, recv_s, recv_l, recv_i, send_c, send_g, send_iq, commands, completer,
+    );
 
     // Cleanup:
     // Input thread as the output thread has commanded.
     input.join().unwrap();
     // Command thread should terminate automatically once the output thread exits.
+    if managed {
+        eprintln!("waiting for the server to stop...");
+    }
     command.join().unwrap().unwrap();
     // Drop the history thread sender so that the history thread terminates.
     drop(send_hq);
-    history.join().unwrap().unwrap();
-    // Kill the tail process so that the logs thread terminates.
-    tail.kill().unwrap();
-    logs.join().unwrap().unwrap();
+    if let Err(e) = history.join().unwrap() {
+        eprintln!("failed to read old logs: {}", e);
+    }
+    // Drop the scan thread sender so that the scan thread stops reading.
+    drop(send_sq);
+    if let Some(Err(e)) = scan.map(|scan| scan.join().unwrap()) {
+        eprintln!("failed to read the state of the server: {}", e);
+    }
+    // Drop the logs thread sender so that the logs thread terminates.
+    drop(send_lq);
+    if let Some(Err(e)) = logs.map(|logs| logs.join().unwrap()) {
+        eprintln!("failed to follow the logs: {}", e);
+    }
 }
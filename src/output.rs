@@ -1,32 +1,106 @@
 use std::borrow::Cow;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::iter;
 use std::mem;
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use crossbeam::channel;
 use crossbeam::channel::{select, Receiver, Sender};
 use lazy_static::lazy_static;
 use regex::Regex;
-use rustbox::{Event, Key, RustBox};
+use rustbox::RustBox;
 
-use crate::config::{Color, Config};
+use crate::alert;
+use crate::chat;
+use crate::complete::Completer;
+use crate::config;
+use crate::config::{Color, Config, FormatCodes};
+use crate::editor::Editor;
+use crate::filter::Filter;
+use crate::format::Log;
+use crate::goto;
+use crate::goto::Dater;
+use crate::history::{Goto, Jump};
+use crate::input::Input;
+use crate::journal::Journal;
+use crate::keys::Action;
+use crate::recall::CommandHistory;
+use crate::status::ServerState;
+use crate::style;
+
+/// The log file that a log is from.
+#[derive(PartialEq, Eq, Debug)]
+pub struct LogFile {
+    /// Path of the file, if the logs are read from one
+    pub path: Option<String>,
+    /// Date of the logs in the file, or of the day that new logs were written
+    pub date: Option<NaiveDate>,
+}
+
+impl LogFile {
+    /// Make the file of new logs written to `path` today.
+    pub fn today(path: Option<String>) -> Arc<LogFile> {
+        Arc::new(LogFile {
+            path,
+            date: Some(Local::now().date_naive()),
+        })
+    }
+
+    /// Replace the file of new logs with one for the next day once the day changes.
+    pub fn keep_today(file: &mut Arc<LogFile>) {
+        if file.date != Some(Local::now().date_naive()) {
+            *file = LogFile::today(file.path.clone());
+        }
+    }
+}
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Line {
-    Log(String),
+    /// A log, and the file that it is from.
+    Log(String, Arc<LogFile>),
     Header(String),
+    /// Output of a command sent through RCON.
+    Response(String),
+    /// A message from better-console itself, such as the exit status of the server.
+    Notice(String),
 }
 
+lazy_static! {
+    /// Matches the messages of the lines of a stack trace after the first one, for log formats
+    /// that repeat the time and level on every line.
+    static ref TRACE_REGEX: Regex =
+        Regex::new(r"^\s*(?:at |\.\.\. \d+ more|Caused by: |Suppressed: )").unwrap();
+}
+
+/// How long the status bar shows a log that triggered an alert.
+const FLASH_TIME: Duration = Duration::from_secs(5);
+
+/// How often to update the session durations in the player panel.
+const PANEL_REFRESH: Duration = Duration::from_secs(60);
+
+/// Smallest number of lines kept in memory, whatever the configuration says.
+const MIN_LINES: usize = 1000;
+
+/// Number of lines read at a time from the lines moved out of memory.
+const RELOAD_LINES: isize = 512;
+
+/// Maximum number of completion candidates shown at once.
+const POPUP_ROWS: isize = 8;
+
 struct Text<'a> {
     text: Cow<'a, str>,
     color: Color,
 }
 
 impl<'a> Text<'a> {
-    fn new(text: Cow<'a, str>, color: Color) -> Text {
+    fn new(text: Cow<'a, str>, color: Color) -> Text<'a> {
         Text { text, color }
     }
 
-    fn normal(text: Cow<'a, str>) -> Text {
+    fn normal(text: Cow<'a, str>) -> Text<'a> {
         Text {
             text,
             color: Color::default(),
@@ -34,26 +108,138 @@ impl<'a> Text<'a> {
     }
 }
 
+/// State of a search through the logs.
+struct Search {
+    /// The query as typed by the user.
+    query: Editor,
+    /// The compiled query, or `None` if the query is empty or invalid.
+    regex: Option<Regex>,
+    /// ID of the line below the first line to consider when the query changes.
+    origin: isize,
+    /// ID of the line containing the current match.
+    found: Option<isize>,
+}
+
+/// A row of the log area of the screen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Row {
+    /// ID of the line shown in the row.
+    id: isize,
+    /// Byte index in the displayed text of the line where the row starts. Only rows after the
+    /// first row of a wrapped line start after 0.
+    start: usize,
+}
+
+/// Candidates for completing a word in the prompt.
+struct Completion {
+    /// Byte index of the start of the word.
+    start: usize,
+    candidates: Vec<String>,
+    /// Index of the candidate currently in the prompt.
+    selected: Option<usize>,
+}
+
+/// What the text typed by the user is for.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Prompt {
+    Command,
+    Search,
+    Filter,
+    /// The date or time to go to.
+    Goto,
+    /// Reverse search through the command history.
+    Recall,
+}
+
+/// Where the keys for moving around go.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Focus {
+    /// The arrow keys move the cursor and recall commands.
+    Prompt,
+    /// The arrow keys scroll the logs.
+    Logs,
+}
+
 struct Console {
     config: Arc<Config>,
     buffer: VecDeque<Line>,
-    input: String,
+    /// ID of the first line in `buffer`. IDs stay the same when lines are added to the front.
+    first: isize,
+    /// Lines that have been moved out of `buffer` to keep it from growing without end.
+    journal: Journal,
+    /// Number of lines after the ones in `buffer` that are only in the journal.
+    spilled_newer: isize,
+    /// Whether the history has no older lines to send.
+    history_end: bool,
+    /// Whether each line in `buffer` continues the line before it, like the lines of a stack trace
+    /// or of a message with several lines. Such lines are grouped with the line they continue.
+    continues: VecDeque<bool>,
+    /// ID of the first line of the group of each line in `buffer`, which is the line's own ID if
+    /// it does not continue another line, or less than `first` if the first line is not in the
+    /// buffer.
+    heads: VecDeque<isize>,
+    /// Whether groups of lines are shown in full, instead of only their first line.
+    expand_groups: bool,
+    /// Whether each line in `buffer` is a log with the same message as the line before it.
+    repeats: VecDeque<bool>,
+    /// Whether runs of repeated logs are shown as their first line with a count.
+    collapse_repeats: bool,
+    /// Whether only chat is shown.
+    chat: bool,
+    /// Where to go back to when switching between showing only chat and all logs, as the ID of
+    /// the bottom line of the other view, or `None` if it follows new logs.
+    other_bottom: Option<isize>,
+    /// Names of the players that have joined or left in the lines in `buffer`.
+    players_seen: HashSet<String>,
+    /// Rows of the lines in `buffer` that pass the filter, in order.
+    view: VecDeque<Row>,
+    filter: Option<Filter>,
+    input: Editor,
+    search: Option<Search>,
+    filter_input: Editor,
+    goto_input: Editor,
+    prompt: Prompt,
+    focus: Focus,
+    commands: CommandHistory,
+    /// Index in `commands` of the command being shown in the prompt.
+    recall: Option<usize>,
+    /// What was typed in the prompt before recalling commands.
+    draft: String,
+    recall_query: Editor,
+    /// Index in `commands` of the command found by reverse search.
+    recall_found: Option<usize>,
+    completer: Completer,
+    completion: Option<Completion>,
+    /// Message to show in the status bar instead of the default status.
+    message: Option<String>,
+    /// State of the server shown in the status bar.
+    server: ServerState,
+    /// When each alert rule was last triggered.
+    alerted: Vec<Option<Instant>>,
+    /// Log that triggered an alert, which is shown in the status bar until the given time.
+    flash: Option<(String, Instant)>,
     scroll: isize,
     hscroll: isize,
+    /// Whether long lines are wrapped onto several rows instead of being scrolled horizontally.
+    wrap: bool,
+    /// Whether the panel listing the players online is shown.
+    show_players: bool,
     width: isize,
     height: isize,
     exit: bool,
     rb: Arc<RustBox>,
     send_c: Sender<String>,
+    send_g: Sender<Goto>,
     send_i: Sender<()>,
 }
 
 impl Console {
     fn mainloop(
         &mut self,
-        recv_h: Receiver<Line>,
+        recv_h: Receiver<Option<Line>>,
+        mut recv_s: Receiver<ServerState>,
         recv_l: Receiver<Line>,
-        recv_i: Receiver<Event>,
+        recv_i: Receiver<Input>,
     ) {
         self.width = self.rb.width() as isize;
         self.height = self.rb.height() as isize;
@@ -61,89 +247,1355 @@ impl Console {
         self.collect_logs(&recv_h, &recv_l);
 
         self.draw_all();
+        let panel_refresh = channel::tick(PANEL_REFRESH);
         while !self.exit {
             self.rb.present();
+            let flash_end = match &self.flash {
+                Some((_, end)) => channel::at(*end),
+                None => channel::never(),
+            };
             select! {
                 recv(recv_l) -> log => {
-                    if self.scroll == self.max_scroll() {
-                        self.scroll += 1;
+                    let at_end = self.at_end();
+                    let log = log.unwrap();
+                    // Only new logs trigger alerts, and not the ones from the history.
+                    self.alert(&log);
+                    if self.server.update(&log, &self.config.log_formats) {
+                        self.draw_status();
+                        self.draw_players();
+                    }
+                    if self.spilled_newer > 0 {
+                        // The lines before this one are not in memory, so this one is not either.
+                        self.spill(log);
+                        continue;
+                    }
+                    self.push_back(log);
+                    let visible = self.is_visible(self.first + self.buffer.len() as isize - 1);
+                    if at_end {
+                        self.evict(true, false);
+                    } else {
+                        // Keep the lines on the screen.
+                        self.evict(false, true);
+                        self.evict(true, true);
+                    }
+                    if visible {
+                        if at_end {
+                            self.scroll = self.max_scroll();
+                        }
+                        self.draw_logs();
                     }
-                    self.buffer.push_back(log.unwrap());
-                    self.draw_logs();
                 }
                 recv(recv_i) -> event => {
                     self.process_event(&recv_h, event.unwrap());
                 }
+                recv(recv_s) -> state => {
+                    // The state from the logs written before starting. The new logs so far were
+                    // written before the end of the file was read, so they are part of it too.
+                    if let Ok(state) = state {
+                        self.server = state;
+                        self.draw_status();
+                        self.draw_players();
+                    }
+                    recv_s = channel::never();
+                }
+                recv(flash_end) -> _ => {
+                    self.flash = None;
+                    self.draw_status();
+                }
+                recv(panel_refresh) -> _ => {
+                    self.draw_players();
+                }
+            }
+        }
+    }
+
+    /// Trigger the alert rules that match a new line.
+    fn alert(&mut self, line: &Line) {
+        let s = match line {
+            Line::Log(s, _) => s,
+            _ => return,
+        };
+        let config = self.config.clone();
+        let log = config.log_formats.parse(s);
+        let now = Instant::now();
+        for (i, rule) in config.alert.iter().enumerate() {
+            if !rule.matches(s, log.as_ref()) {
+                continue;
+            }
+            if let Some(last) = self.alerted[i] {
+                if now.duration_since(last) < Duration::from_secs(rule.cooldown) {
+                    continue;
+                }
+            }
+            self.alerted[i] = Some(now);
+
+            if rule.bell {
+                alert::ring_bell();
+            }
+            if rule.flash {
+                let text = self.format_line(line).iter().map(|t| &*t.text).collect();
+                self.flash = Some((text, now + FLASH_TIME));
+                self.draw_status();
+            }
+            if let Err(e) = rule.run_command(s, log.as_ref()) {
+                self.message = Some(format!("failed to run alert command: {}", e));
+                self.draw_status();
             }
         }
     }
 
-    fn collect_logs(&mut self, recv_h: &Receiver<Line>, recv_l: &Receiver<Line>) {
+    fn collect_logs(&mut self, recv_h: &Receiver<Option<Line>>, recv_l: &Receiver<Line>) {
         // Collect enough logs
-        while (self.buffer.len() as isize) < self.height - 2 {
+        while (self.view.len() as isize) < self.log_height() {
             select! {
                 recv(recv_h) -> log => {
-                    if let Ok(log) = log {
-                        self.buffer.push_front(log);
+                    if let Ok(Some(log)) = log {
+                        self.push_front(log);
                     } else {
+                        self.history_end = true;
                         return;
                     }
                 }
                 recv(recv_l) -> log => {
-                    self.buffer.push_back(log.unwrap());
+                    let log = log.unwrap();
+                    self.alert(&log);
+                    self.server.update(&log, &self.config.log_formats);
+                    self.push_back(log);
                 }
             }
         }
     }
 
-    fn process_event(&mut self, recv_h: &Receiver<Line>, event: Event) {
+    fn process_event(&mut self, recv_h: &Receiver<Option<Line>>, event: Input) {
         let height = self.height;
         let vert_move = self.config.vertical_move;
         let horiz_move = self.config.horizontal_move;
+        // When the command line is empty, the keys for moving the cursor scroll the logs instead.
+        let logs = self.focus == Focus::Logs;
+        let empty = logs || self.input.is_empty();
+        let action = self.config.keys.get(event);
+        if action != Some(Action::Complete) && self.completion.take().is_some() {
+            // Hide the completion popup.
+            self.draw_logs();
+        }
         match event {
-            Event::ResizeEvent(w, h) => {
+            Input::Resize(w, h) => {
                 self.width = w as isize;
                 self.height = h as isize;
+                if self.wrap {
+                    // The lines wrap at different places now.
+                    self.rebuild_view(recv_h);
+                }
                 self.draw_all();
             }
-            Event::KeyEvent(key) => match key {
-                Key::Ctrl('q') => {
-                    self.exit = true;
+            _ if action == Some(Action::Quit) => {
+                self.exit = true;
+                return;
+            }
+            event if self.prompt == Prompt::Search => self.process_search_key(recv_h, event),
+            event if self.prompt == Prompt::Filter => self.process_filter_key(recv_h, event),
+            event if self.prompt == Prompt::Goto => self.process_goto_key(recv_h, event),
+            event if self.prompt == Prompt::Recall => self.process_recall_key(event),
+            event => match action {
+                Some(Action::Search) => self.start_search(),
+                Some(Action::Filter) => self.start_filter(),
+                Some(Action::Goto) => self.start_goto(),
+                Some(Action::HistorySearch) => self.start_recall(),
+                Some(Action::SearchOlder) => self.search_next(recv_h, true),
+                Some(Action::SearchNewer) => self.search_next(recv_h, false),
+                Some(Action::ToggleFocus) => {
+                    self.set_focus(if logs { Focus::Prompt } else { Focus::Logs })
+                }
+                Some(Action::ToggleWrap) => {
+                    self.wrap = !self.wrap;
+                    self.hscroll = 0;
+                    self.rebuild_view(recv_h);
+                    self.draw_logs();
+                }
+                Some(Action::ToggleTraces) => {
+                    self.expand_groups = !self.expand_groups;
+                    self.rebuild_view(recv_h);
+                    self.draw_logs();
+                }
+                Some(Action::ToggleRepeats) => {
+                    self.collapse_repeats = !self.collapse_repeats;
+                    self.rebuild_view(recv_h);
+                    self.draw_logs();
+                }
+                Some(Action::ToggleChat) => {
+                    // Each view keeps its own scroll position.
+                    let bottom = if self.at_end() {
+                        None
+                    } else {
+                        Some(self.bottom_id())
+                    };
+                    let other = mem::replace(&mut self.other_bottom, bottom);
+                    self.chat = !self.chat;
+                    self.rebuild_view(recv_h);
+                    self.scroll_to_bottom(other);
+                    self.draw_logs();
+                }
+                Some(Action::TogglePlayers) => {
+                    self.show_players = !self.show_players;
+                    if self.wrap {
+                        // The logs are narrower or wider now.
+                        self.rebuild_view(recv_h);
+                    }
+                    self.draw_logs();
+                }
+                Some(Action::StopSearch) | Some(Action::Cancel) => self.stop_search(),
+                Some(Action::ScrollUp) if logs => self.scroll(recv_h, -vert_move),
+                Some(Action::ScrollDown) if logs => self.scroll(recv_h, vert_move),
+                Some(Action::ScrollUp) | Some(Action::HistoryOlder) => self.recall(true),
+                Some(Action::ScrollDown) | Some(Action::HistoryNewer) => self.recall(false),
+                Some(Action::ScrollLeft) if empty => self.scroll_h(-horiz_move),
+                Some(Action::ScrollRight) if empty => self.scroll_h(horiz_move),
+                Some(Action::ScrollPageUp) => {
+                    self.set_focus(Focus::Logs);
+                    self.scroll(recv_h, -height / 2);
+                }
+                Some(Action::ScrollPageDown) => {
+                    self.set_focus(Focus::Logs);
+                    self.scroll(recv_h, height / 2);
+                }
+                Some(Action::ScrollEnd) if empty => self.scroll_to_end(recv_h),
+                Some(Action::Submit) => self.submit(),
+                Some(Action::Complete) => self.complete(),
+                // The other keys, and the scrolling keys when the prompt is in use, edit the
+                // prompt.
+                _ => {
+                    if self.input.process(event) {
+                        self.focus = Focus::Prompt;
+                        self.draw_input();
+                    }
+                }
+            },
+        }
+        // Tell the input thread to keep going.
+        self.send_i.send(()).unwrap();
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        if self.focus != focus {
+            self.focus = focus;
+            self.draw_input();
+        }
+    }
+
+    /// Send the command in the prompt to the server.
+    fn submit(&mut self) {
+        let command = self.input.take();
+        if let Err(e) = self.commands.add(&command) {
+            self.message = Some(format!("failed to save command history: {}", e));
+            self.draw_status();
+        }
+        self.send_c.send(command).unwrap();
+        self.recall = None;
+        self.focus = Focus::Prompt;
+        self.draw_input();
+    }
+
+    /// Complete the word before the cursor, or select the next candidate if there are several.
+    fn complete(&mut self) {
+        self.focus = Focus::Prompt;
+        if let Some(completion) = &mut self.completion {
+            let selected = completion
+                .selected
+                .map_or(0, |i| (i + 1) % completion.candidates.len());
+            completion.selected = Some(selected);
+            let candidate = &completion.candidates[selected];
+            self.input
+                .replace_before_cursor(completion.start, candidate);
+        } else {
+            let (start, mut candidates) = self.completer.complete(self.input.before_cursor());
+            match candidates.len() {
+                0 => return,
+                1 => {
+                    let text = format!("{} ", candidates[0]);
+                    self.input.replace_before_cursor(start, &text);
+                }
+                _ => {
+                    candidates.sort_unstable();
+                    candidates.dedup();
+                    let word = &self.input.before_cursor()[start..];
+                    if let Some(prefix) = extended_prefix(word, &candidates) {
+                        let prefix = prefix.to_string();
+                        self.input.replace_before_cursor(start, &prefix);
+                    }
+                    self.completion = Some(Completion {
+                        start,
+                        candidates,
+                        selected: None,
+                    });
+                }
+            }
+        }
+        self.draw_logs();
+        self.draw_input();
+    }
+
+    /// Show the previous (if `older`) or next command from the history in the prompt.
+    fn recall(&mut self, older: bool) {
+        let len = self.commands.len();
+        let index = match self.recall {
+            None if older && len > 0 => {
+                self.draft = self.input.text().to_string();
+                len - 1
+            }
+            None => return,
+            Some(i) if older => i.saturating_sub(1),
+            Some(i) => i + 1,
+        };
+        if index < len {
+            self.recall = Some(index);
+            let command = self.commands.get(index).unwrap().to_string();
+            self.input.set_text(command);
+        } else {
+            // Back to what was being typed.
+            self.recall = None;
+            let draft = mem::take(&mut self.draft);
+            self.input.set_text(draft);
+        }
+        self.draw_input();
+    }
+
+    fn process_recall_key(&mut self, event: Input) {
+        let len = self.commands.len();
+        let action = self.config.keys.get(event);
+        match action {
+            Some(Action::HistorySearch) => {
+                let before = self.recall_found.unwrap_or(len);
+                if let Some(i) = self.commands.find(self.recall_query.text(), before) {
+                    self.recall_found = Some(i);
+                }
+            }
+            Some(Action::Submit) | Some(Action::StopSearch) => {
+                // Like bash, Enter runs the command that was found, while Esc allows editing it.
+                // Both keys can be rebound.
+                self.prompt = Prompt::Command;
+                self.message = None;
+                if let Some(i) = self.recall_found {
+                    let command = self.commands.get(i).unwrap().to_string();
+                    self.input.set_text(command);
+                    self.recall = Some(i);
+                }
+                if action == Some(Action::Submit) {
+                    self.submit();
+                }
+                self.draw_status();
+            }
+            Some(Action::Cancel) => {
+                self.prompt = Prompt::Command;
+                self.message = None;
+                self.draw_status();
+            }
+            _ => {
+                let old = self.recall_query.text().to_string();
+                if !self.recall_query.process(event) || self.recall_query.text() == old {
+                    return self.draw_input();
+                }
+                // Search again, starting with the command that is currently found.
+                let before = self.recall_found.map_or(len, |i| i + 1);
+                let found = self.commands.find(self.recall_query.text(), before);
+                if found.is_some() || self.recall_query.is_empty() {
+                    self.recall_found = found;
+                }
+            }
+        }
+        self.draw_input();
+    }
+
+    /// Start searching backwards through the command history.
+    fn start_recall(&mut self) {
+        self.recall_query.take();
+        self.recall_found = None;
+        self.prompt = Prompt::Recall;
+        self.focus = Focus::Prompt;
+        self.message = Some(self.config.keys.hint(&[
+            (&[Action::HistorySearch], "older"),
+            (&[Action::Submit], "run"),
+            (&[Action::StopSearch], "edit"),
+            (&[Action::Cancel], "cancel"),
+        ]));
+        self.draw_input();
+        self.draw_status();
+    }
+
+    fn process_search_key(&mut self, recv_h: &Receiver<Option<Line>>, event: Input) {
+        let search = self.search.as_mut().unwrap();
+        match self.config.keys.get(event) {
+            Some(Action::Submit) => {
+                self.prompt = Prompt::Command;
+                self.message = None;
+                self.draw_input();
+                self.draw_status();
+                return;
+            }
+            Some(Action::StopSearch) | Some(Action::Cancel) => {
+                self.stop_search();
+                return;
+            }
+            Some(Action::ScrollUp) | Some(Action::SearchOlder) => {
+                return self.search_next(recv_h, true)
+            }
+            Some(Action::ScrollDown) | Some(Action::SearchNewer) => {
+                return self.search_next(recv_h, false)
+            }
+            _ => {
+                let old = search.query.text().to_string();
+                if !search.query.process(event) {
                     return;
                 }
-                Key::Up => self.scroll(recv_h, -vert_move),
-                Key::Down => self.scroll(recv_h, vert_move),
-                Key::Left => self.scroll_h(-horiz_move),
-                Key::Right => self.scroll_h(horiz_move),
-                Key::PageUp => self.scroll(recv_h, -height / 2),
-                Key::PageDown => self.scroll(recv_h, height / 2),
-                Key::End => self.scroll_to_end(),
-                Key::Char(c) => {
-                    self.input.push(c);
+                if search.query.text() == old {
+                    // Only the cursor moved.
                     self.draw_input();
+                    return;
                 }
-                Key::Backspace => {
-                    self.input.pop();
+            }
+        }
+        self.draw_input();
+
+        // The query changed, so search again from where the search started.
+        let search = self.search.as_mut().unwrap();
+        if search.query.is_empty() {
+            search.regex = None;
+            search.found = None;
+            self.message = None;
+        } else {
+            match Regex::new(search.query.text()) {
+                Ok(regex) => {
+                    search.regex = Some(regex);
+                    let origin = search.origin;
+                    let found = self.find(recv_h, origin, true);
+                    self.search.as_mut().unwrap().found = found;
+                    self.message = None;
+                    if let Some(id) = found {
+                        self.show_match(id);
+                    } else {
+                        self.message = Some("no match".to_string());
+                    }
+                }
+                Err(e) => {
+                    // Keep showing the matches of the last valid query.
+                    let msg = config::regex_error(&e);
+                    self.message = Some(format!("invalid regex: {}", msg));
+                }
+            }
+        }
+        self.draw_logs();
+        self.draw_status();
+    }
+
+    /// Start typing a new search query.
+    fn start_search(&mut self) {
+        self.search = Some(Search {
+            query: Editor::default(),
+            regex: None,
+            origin: self.bottom_id() + 1,
+            found: None,
+        });
+        self.prompt = Prompt::Search;
+        self.message = Some(self.config.keys.hint(&[
+            (&[Action::Submit], "done"),
+            (&[Action::StopSearch, Action::Cancel], "cancel"),
+            (&[Action::ScrollUp, Action::SearchOlder], "previous match"),
+            (&[Action::ScrollDown, Action::SearchNewer], "next match"),
+        ]));
+        self.draw_all();
+    }
+
+    /// Stop searching and remove the highlighting of matches.
+    fn stop_search(&mut self) {
+        if self.search.is_some() {
+            self.search = None;
+            self.prompt = Prompt::Command;
+            self.message = None;
+            self.draw_all();
+        }
+    }
+
+    /// Jump to the previous (older) or next (newer) match of the current search.
+    fn search_next(&mut self, recv_h: &Receiver<Option<Line>>, older: bool) {
+        let from = match &self.search {
+            Some(Search {
+                regex: Some(_),
+                found: Some(id),
+                ..
+            }) => *id,
+            Some(Search {
+                regex: Some(_),
+                origin,
+                ..
+            }) => *origin,
+            _ => return,
+        };
+        let bottom = self.bottom_id();
+        if let Some(id) = self.find(recv_h, from, older) {
+            self.search.as_mut().unwrap().found = Some(id);
+            self.message = None;
+            self.show_match(id);
+        } else {
+            self.message = Some(if older {
+                "no older match".to_string()
+            } else {
+                "no newer match".to_string()
+            });
+            if !self.is_resident(bottom) {
+                // The lines on the screen were moved out of memory while searching.
+                self.reload_at(recv_h, bottom);
+            }
+        }
+        self.draw_logs();
+        self.draw_status();
+    }
+
+    /// Find the closest visible line before (if `older`) or after `from` that matches the current
+    /// search, and return its ID. Fetches more old logs until a match is found or the history runs
+    /// out.
+    fn find(&mut self, recv_h: &Receiver<Option<Line>>, from: isize, older: bool) -> Option<isize> {
+        let regex = self.search.as_ref()?.regex.clone()?;
+        // Position in `view` of the first row to check
+        let start = if older {
+            self.view.partition_point(|r| r.id < from) as isize - 1
+        } else {
+            self.view.partition_point(|r| r.id <= from) as isize
+        };
+        // Only check the first row of each line.
+        let matches = |this: &Self, i: isize| {
+            let row = this.view[i as usize];
+            row.start == 0 && regex.is_match(&this.display_text(row.id))
+        };
+        if older {
+            let mut i = start;
+            loop {
+                while i < 0 {
+                    match self.fetch_older(recv_h) {
+                        Some(added) => i += added,
+                        // No more logs
+                        None => return None,
+                    }
+                }
+                if matches(self, i) {
+                    return Some(self.view[i as usize].id);
+                }
+                i -= 1;
+            }
+        } else {
+            let mut i = start;
+            loop {
+                while i >= self.view.len() as isize {
+                    // Continue after the last line checked once the newer lines are read.
+                    let last = self.view.back().map(|r| r.id);
+                    self.fetch_newer()?;
+                    i = match last {
+                        Some(last) => self.view.partition_point(|r| r.id <= last) as isize,
+                        None => 0,
+                    };
+                }
+                if matches(self, i) {
+                    return Some(self.view[i as usize].id);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    /// Scroll so that the line with the given ID and its first match are visible.
+    fn show_match(&mut self, id: isize) {
+        let h = self.log_height();
+        let mut pos = self.view.partition_point(|r| r.id < id);
+        if self.view.get(pos).map(|r| r.id) != Some(id) {
+            return;
+        }
+        let idx = id - self.first;
+
+        let text = self.display_text(id);
+        let regex = self.search.as_ref().and_then(|s| s.regex.as_ref());
+        let found = regex.and_then(|regex| regex.find(&text));
+        if let (Some(m), true) = (&found, self.wrap) {
+            // Show the row containing the start of the match.
+            while matches!(self.view.get(pos + 1), Some(r) if r.id == id && r.start <= m.start()) {
+                pos += 1;
+            }
+        }
+        let pos = pos as isize;
+        if pos < self.scroll || pos >= self.scroll + h {
+            self.scroll = (pos - h / 2).max(0).min(self.max_scroll());
+        }
+
+        let is_header = matches!(self.buffer[idx as usize], Line::Header(_));
+        if !self.wrap && !is_header {
+            if let Some(m) = found {
+                let col = text[..m.start()].chars().count() as isize;
+                let len = m.as_str().chars().count() as isize;
+                if col < self.hscroll || col + len > self.hscroll + self.log_width() {
+                    let step = self.config.horizontal_move.max(1);
+                    self.hscroll = (col - self.log_width() / 2).max(0) / step * step;
+                }
+            }
+        }
+    }
+
+    fn process_filter_key(&mut self, recv_h: &Receiver<Option<Line>>, event: Input) {
+        match self.config.keys.get(event) {
+            Some(Action::Submit) => {
+                let filter = if self.filter_input.text().trim().is_empty() {
+                    None
+                } else {
+                    match Filter::parse(self.filter_input.text()) {
+                        Ok(filter) => Some(filter),
+                        Err(e) => {
+                            self.message = Some(e);
+                            self.draw_status();
+                            return;
+                        }
+                    }
+                };
+                self.prompt = Prompt::Command;
+                self.message = None;
+                self.set_filter(recv_h, filter);
+                self.draw_all();
+            }
+            Some(Action::StopSearch) | Some(Action::Cancel) => {
+                self.prompt = Prompt::Command;
+                self.message = None;
+                self.draw_all();
+            }
+            _ => {
+                if self.filter_input.process(event) {
                     self.draw_input();
                 }
-                Key::Enter => {
-                    let mut command = String::new();
-                    mem::swap(&mut command, &mut self.input);
-                    self.send_c.send(command).unwrap();
+            }
+        }
+    }
+
+    /// Start editing the filter.
+    fn start_filter(&mut self) {
+        let text = match &self.filter {
+            Some(filter) => filter.text.clone(),
+            None => String::new(),
+        };
+        self.filter_input.set_text(text);
+        self.prompt = Prompt::Filter;
+        let keys = self.config.keys.hint(&[
+            (&[Action::Submit], "apply"),
+            (&[Action::StopSearch, Action::Cancel], "cancel"),
+        ]);
+        self.message = Some(format!(
+            "{}; e.g. level:WARN+ thread:\"Server thread\" regex",
+            keys
+        ));
+        self.draw_all();
+    }
+
+    /// Replace the filter and rebuild the view.
+    fn set_filter(&mut self, recv_h: &Receiver<Option<Line>>, filter: Option<Filter>) {
+        self.filter = filter;
+        self.rebuild_view(recv_h);
+    }
+
+    fn process_goto_key(&mut self, recv_h: &Receiver<Option<Line>>, event: Input) {
+        match self.config.keys.get(event) {
+            Some(Action::Submit) => {
+                let now = Local::now().naive_local();
+                match goto::parse_target(self.goto_input.text(), now) {
+                    Ok(time) => {
+                        self.prompt = Prompt::Command;
+                        self.message = None;
+                        self.goto(recv_h, time);
+                        self.draw_all();
+                    }
+                    Err(e) => {
+                        self.message = Some(e);
+                        self.draw_status();
+                    }
+                }
+            }
+            Some(Action::StopSearch) | Some(Action::Cancel) => {
+                self.prompt = Prompt::Command;
+                self.message = None;
+                self.draw_all();
+            }
+            _ => {
+                if self.goto_input.process(event) {
                     self.draw_input();
                 }
-                _ => (),
+            }
+        }
+    }
+
+    /// Start typing the date or time to go to.
+    fn start_goto(&mut self) {
+        self.goto_input.take();
+        self.prompt = Prompt::Goto;
+        let keys = self.config.keys.hint(&[
+            (&[Action::Submit], "go"),
+            (&[Action::StopSearch, Action::Cancel], "cancel"),
+        ]);
+        self.message = Some(format!("{}; e.g. 2024-05-03 14:20, 14:20 or -2h", keys));
+        self.draw_all();
+    }
+
+    /// Show the first line at or after `time`. The lines that were already read are searched
+    /// first, and then the history finds the log file with the time.
+    fn goto(&mut self, recv_h: &Receiver<Option<Line>>, time: NaiveDateTime) {
+        if let Some(id) = self.find_time(time) {
+            return self.show_at_top(recv_h, id);
+        }
+        // If the history skips to older logs, they go before all of the lines read so far.
+        let oldest = match self.journal.first() {
+            Some(id) => id.min(self.first),
+            None => self.first,
+        };
+        let (reply, recv_r) = channel::bounded(0);
+        let req = Goto {
+            time,
+            next_id: oldest - 1,
+            after: self.log_height().max(0) as usize,
+            reply,
+        };
+        if self.send_g.send(req).is_err() {
+            self.message = Some("old logs cannot be read".to_string());
+            return;
+        }
+        match recv_r.recv() {
+            Ok(Ok(Jump::Sent(id))) => self.show_at_top(recv_h, id),
+            Ok(Ok(Jump::Skipped(id))) => self.skip_to(recv_h, oldest - 1, id),
+            Ok(Err(e)) => self.message = Some(e),
+            Err(_) => self.message = Some("old logs cannot be read".to_string()),
+        }
+    }
+
+    /// Find the first line at or after `time` by going back from the newest line, as long as
+    /// the lines are from the current log file and were read. Returns the ID after the newest
+    /// line if they are all before the time, or `None` if it cannot tell.
+    fn find_time(&mut self, time: NaiveDateTime) -> Option<isize> {
+        let now = Local::now().naive_local();
+        let mut dater = Dater::new(now.date(), Some(now.time()), true);
+        let end = self.first + self.buffer.len() as isize + self.spilled_newer;
+        let mut found = end;
+        // Lines read from the journal, which end with the line with the ID `id`
+        let mut lines = Vec::new();
+        let mut id = end;
+        loop {
+            id -= 1;
+            let line = if self.is_resident(id) {
+                lines.clear();
+                self.buffer[(id - self.first) as usize].clone()
+            } else {
+                if lines.is_empty() {
+                    let start = self.journal.written_before(id + 1, RELOAD_LINES);
+                    if start > id {
+                        return None;
+                    }
+                    lines = match self.journal.read(start..id + 1) {
+                        Ok(lines) => lines,
+                        Err(e) => {
+                            self.message = Some(format!("failed to read old lines: {}", e));
+                            return None;
+                        }
+                    };
+                }
+                lines.pop().unwrap()
+            };
+            match line {
+                Line::Log(s, _) => {
+                    if let Some(clock) = goto::clock(&s, &self.config.log_formats) {
+                        if dater.next(clock) < time {
+                            return Some(found);
+                        }
+                        found = id;
+                    }
+                }
+                // The lines before are from another log file, or the history skipped to them.
+                Line::Header(_) => return None,
+                Line::Notice(_) if id < 0 => return None,
+                Line::Notice(_) | Line::Response(_) => {}
+            }
+        }
+    }
+
+    /// Scroll so that the line with the given ID is at the top of the screen if possible, reading
+    /// it and the lines after it again if they were moved out of memory.
+    fn show_at_top(&mut self, recv_h: &Receiver<Option<Line>>, id: isize) {
+        let h = self.log_height();
+        let end = self.first + self.buffer.len() as isize + self.spilled_newer;
+        if id >= end {
+            return self.scroll_to_end(recv_h);
+        }
+        let in_memory = self.first + self.buffer.len() as isize;
+        if !self.is_resident(id) || (id + h > in_memory && self.spilled_newer > 0) {
+            self.reload_at(recv_h, (id + h).min(end) - 1);
+        }
+        let pos = self.view.partition_point(|r| r.id < id) as isize;
+        self.scroll = pos.min(self.max_scroll());
+    }
+
+    /// Move all lines out of memory after the history skipped to older logs, and read the lines
+    /// that it sends from the notice with the ID `notice` on, to show the line with the ID `id`.
+    fn skip_to(&mut self, recv_h: &Receiver<Option<Line>>, notice: isize, id: isize) {
+        // The lines that were sent before the notice are not wanted any more.
+        let line = loop {
+            match recv_h.recv() {
+                Ok(Some(line @ Line::Notice(_))) => break line,
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        };
+        if let Err(e) = self.journal.write(self.first, &self.buffer) {
+            self.message = Some(format!("failed to save lines: {}", e));
+            return;
+        }
+        let end = self.first + self.buffer.len() as isize + self.spilled_newer;
+        self.buffer.clear();
+        self.continues.clear();
+        self.heads.clear();
+        self.repeats.clear();
+        self.view.clear();
+        self.first = notice + 1;
+        self.spilled_newer = end - self.first;
+        self.history_end = false;
+        self.push_front(line);
+        while self.first > id {
+            if self.fetch_older(recv_h).is_none() {
+                break;
+            }
+        }
+        self.show_at_top(recv_h, id);
+    }
+
+    /// Rebuild the view after the filter or the wrapping of lines has changed, keeping the bottom
+    /// line in place if possible.
+    fn rebuild_view(&mut self, recv_h: &Receiver<Option<Line>>) {
+        let bottom = if self.at_end() {
+            None
+        } else {
+            Some(self.bottom_id())
+        };
+        let mut view = VecDeque::new();
+        for id in self.first..self.first + self.buffer.len() as isize {
+            if self.is_visible(id) {
+                view.extend(self.rows(id));
+            }
+        }
+        self.view = view;
+
+        // Make sure there are enough lines to fill the screen.
+        let h = self.log_height();
+        while (self.view.len() as isize) < h {
+            if self.fetch_older(recv_h).is_none() {
+                // No more logs
+                break;
+            }
+        }
+        self.scroll_to_bottom(bottom);
+    }
+
+    /// Check whether the view shows the newest logs, and should follow new ones.
+    fn at_end(&self) -> bool {
+        self.spilled_newer == 0 && self.scroll == self.max_scroll()
+    }
+
+    /// Check whether the line with the given ID is in memory.
+    fn is_resident(&self, id: isize) -> bool {
+        id >= self.first && id < self.first + self.buffer.len() as isize
+    }
+
+    /// Read older lines, from the journal if they were moved out of memory, or else from the
+    /// history. Returns the number of rows added, or `None` if there are no older lines.
+    fn fetch_older(&mut self, recv_h: &Receiver<Option<Line>>) -> Option<isize> {
+        let end = self.first;
+        let start = self.journal.written_before(end, RELOAD_LINES);
+        let added = if start < end {
+            match self.journal.read(start..end) {
+                Ok(lines) => lines.into_iter().rev().map(|l| self.push_front(l)).sum(),
+                Err(e) => {
+                    self.message = Some(format!("failed to read old lines: {}", e));
+                    return None;
+                }
+            }
+        } else {
+            if self.history_end {
+                return None;
+            }
+            match recv_h.recv() {
+                Ok(Some(line)) => self.push_front(line),
+                _ => {
+                    self.history_end = true;
+                    return None;
+                }
+            }
+        };
+        self.evict(false, false);
+        Some(added)
+    }
+
+    /// Read the newer lines that were moved out of memory. Returns the number of rows added, or
+    /// `None` if there are no newer lines.
+    fn fetch_newer(&mut self) -> Option<isize> {
+        if self.spilled_newer == 0 {
+            return None;
+        }
+        let start = self.first + self.buffer.len() as isize;
+        let end = self
+            .journal
+            .written_after(start, RELOAD_LINES.min(self.spilled_newer));
+        let lines = match self.journal.read(start..end) {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.message = Some(format!("failed to read old lines: {}", e));
+                return None;
+            }
+        };
+        self.spilled_newer -= lines.len() as isize;
+        let added = lines.into_iter().map(|l| self.push_back(l)).sum();
+        self.evict(true, false);
+        Some(added)
+    }
+
+    /// Write a new line to the journal without keeping it in memory.
+    fn spill(&mut self, line: Line) {
+        self.learn(&line);
+        let id = self.first + self.buffer.len() as isize + self.spilled_newer;
+        match self.journal.write(id, iter::once(&line)) {
+            Ok(()) => self.spilled_newer += 1,
+            Err(e) => self.message = Some(format!("failed to save new line: {}", e)),
+        }
+        // The scroll position may be shown in the status bar.
+        self.draw_status();
+    }
+
+    /// Move lines out of memory into the journal if there are too many, from the front or the
+    /// back of the buffer. If `keep_screen`, the lines on the screen are kept. Groups and runs are
+    /// not split if possible, so that they are shown the same way when they are read again.
+    fn evict(&mut self, front: bool, keep_screen: bool) {
+        let limit = self.config.max_lines.max(MIN_LINES);
+        let len = self.buffer.len();
+        if len <= limit {
+            return;
+        }
+        // Move out more than needed, so that this is not done for every line.
+        let count = len - limit + limit / 8;
+        // Look for the start of a group this far at most, since a log format that is not
+        // configured makes every line part of one group.
+        let max = RELOAD_LINES as usize;
+        if front {
+            let mut end = count;
+            while end < (count + max).min(len) && self.joins_previous(end) {
+                end += 1;
+            }
+            if keep_screen {
+                let top = match self.view.get(self.scroll as usize) {
+                    Some(row) => (row.id - self.first) as usize,
+                    None => len,
+                };
+                end = end.min(top);
+            }
+            if end == 0 {
+                return;
+            }
+            if let Err(e) = self.journal.write(self.first, self.buffer.range(..end)) {
+                self.message = Some(format!("failed to save old lines: {}", e));
+                return;
+            }
+            self.buffer.drain(..end);
+            self.continues.drain(..end);
+            self.heads.drain(..end);
+            self.repeats.drain(..end);
+            self.first += end as isize;
+            // The first line never repeats, since the line before it is not known.
+            if let Some(repeat) = self.repeats.front_mut() {
+                *repeat = false;
+            }
+            // The rest of a group or run that was split is shown on its own.
+            let rest = (1..self.buffer.len())
+                .find(|&i| !self.joins_previous(i))
+                .unwrap_or(self.buffer.len());
+            let end = self.first + rest as isize;
+            let mut removed = 0;
+            while matches!(self.view.front(), Some(row) if row.id < end) {
+                self.view.pop_front();
+                removed += 1;
+            }
+            for id in (self.first..end).rev() {
+                if self.is_visible(id) {
+                    for row in self.rows(id).into_iter().rev() {
+                        self.view.push_front(row);
+                        removed -= 1;
+                    }
+                }
+            }
+            self.scroll = (self.scroll - removed).max(0);
+        } else {
+            let mut start = len - count;
+            while start > (len - count).saturating_sub(max) && self.joins_previous(start) {
+                start -= 1;
+            }
+            if keep_screen {
+                let bottom = (self.bottom_id() - self.first + 1).max(0) as usize;
+                start = start.max(bottom);
+            }
+            if start == len {
+                return;
+            }
+            let first = self.first + start as isize;
+            if let Err(e) = self.journal.write(first, self.buffer.range(start..)) {
+                self.message = Some(format!("failed to save new lines: {}", e));
+                return;
+            }
+            self.buffer.truncate(start);
+            self.continues.truncate(start);
+            self.heads.truncate(start);
+            self.repeats.truncate(start);
+            self.spilled_newer += (len - start) as isize;
+            while matches!(self.view.back(), Some(row) if row.id >= first) {
+                self.view.pop_back();
+            }
+            if !self.buffer.is_empty() {
+                // The summary of a group or run that was split changes.
+                self.show_last_group();
+            }
+            self.scroll = self.scroll.min(self.max_scroll());
+        }
+    }
+
+    /// Check whether the line at `index` in the buffer continues or repeats the line before it.
+    fn joins_previous(&self, index: usize) -> bool {
+        self.continues[index] || self.repeats[index]
+    }
+
+    /// Move all lines out of memory, and read them again so that the line with the ID `bottom` is
+    /// at the bottom of the screen.
+    fn reload_at(&mut self, recv_h: &Receiver<Option<Line>>, bottom: isize) {
+        if let Err(e) = self.journal.write(self.first, &self.buffer) {
+            self.message = Some(format!("failed to save lines: {}", e));
+            return;
+        }
+        let end = self.first + self.buffer.len() as isize + self.spilled_newer;
+        self.buffer.clear();
+        self.continues.clear();
+        self.heads.clear();
+        self.repeats.clear();
+        self.view.clear();
+        self.first = bottom + 1;
+        self.spilled_newer = end - self.first;
+        while (self.view.len() as isize) < self.log_height() {
+            if self.fetch_older(recv_h).is_none() {
+                break;
+            }
+        }
+        self.scroll_to_bottom(Some(bottom));
+    }
+
+    /// Scroll so that the line with the ID `bottom` is at the bottom of the screen if possible, or
+    /// to the end if it is `None`.
+    fn scroll_to_bottom(&mut self, bottom: Option<isize>) {
+        let h = self.log_height();
+        self.scroll = match bottom {
+            Some(bottom) => {
+                // The last row of the bottom line
+                let pos = self.view.partition_point(|r| r.id <= bottom) as isize - 1;
+                (pos + 1 - h).max(0).min(self.max_scroll())
+            }
+            None => self.max_scroll(),
+        };
+    }
+
+    /// Check whether the line with the given ID passes the filter. Lines in a group or a collapsed
+    /// run of repeats are shown if the first line is.
+    fn is_visible(&self, id: isize) -> bool {
+        let id = self.head(id).unwrap_or(id);
+        let id = match self.run_head(id) {
+            Some(head) if self.collapse_repeats => head,
+            _ => id,
+        };
+        let line = &self.buffer[(id - self.first) as usize];
+        if self.chat && self.chat_name(line).is_none() {
+            return false;
+        }
+        match &self.filter {
+            Some(filter) => filter.matches(line, &self.config.log_formats),
+            None => true,
+        }
+    }
+
+    /// Find the name of the player in `line` if it is chat, as a range in its message.
+    fn chat_name(&self, line: &Line) -> Option<Range<usize>> {
+        match line {
+            Line::Log(s, _) => {
+                let log = self.config.log_formats.parse(s)?;
+                chat::find_name(log.message, &self.players_seen)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether `line` continues the line before it.
+    fn is_continuation(&self, line: &Line) -> bool {
+        match line {
+            Line::Log(s, _) => match self.config.log_formats.parse(s) {
+                Some(log) => TRACE_REGEX.is_match(log.message),
+                // Lines that are not in a log format are part of the log before them.
+                None => true,
             },
-            _ => (),
+            _ => false,
+        }
+    }
+
+    /// Get the ID of the first line of the group of the line with the given ID, or `None` if the
+    /// line does not continue another one, or if the line it continues is not known yet.
+    fn head(&self, id: isize) -> Option<isize> {
+        let i = (id - self.first) as usize;
+        let head = self.heads[i];
+        if !self.continues[i] || head < self.first {
+            return None;
+        }
+        match self.buffer[(head - self.first) as usize] {
+            Line::Log(_, _) => Some(head),
+            _ => None,
+        }
+    }
+
+    /// Get the number of lines after the line with the given ID that continue it, which is 0 if
+    /// it continues another line itself.
+    fn group_len(&self, id: isize) -> usize {
+        let i = (id - self.first) as usize;
+        if self.continues[i] || !matches!(self.buffer[i], Line::Log(_, _)) {
+            return 0;
+        }
+        // The lines before the group and in it have heads up to this line, and the lines after
+        // it have later heads.
+        self.heads.partition_point(|&head| head <= id) - i - 1
+    }
+
+    /// Check whether `line` is a log with the same message as `prev`, ignoring the time.
+    fn is_repeat(&self, line: &Line, prev: &Line) -> bool {
+        let formats = &self.config.log_formats;
+        let (log, prev) = match (line, prev) {
+            (Line::Log(a, _), Line::Log(b, _)) => match (formats.parse(a), formats.parse(b)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        (log.thread, log.level, log.logger, log.message)
+            == (prev.thread, prev.level, prev.logger, prev.message)
+            && !TRACE_REGEX.is_match(log.message)
+    }
+
+    /// Get the ID of the first line of the run of repeated logs of the line with the given ID, or
+    /// `None` if the line does not repeat the one before it.
+    fn run_head(&self, id: isize) -> Option<isize> {
+        let mut i = (id - self.first) as usize;
+        if !self.repeats[i] {
+            return None;
+        }
+        // The first line in the buffer never repeats, since the line before it is not known.
+        while self.repeats[i] {
+            i -= 1;
+        }
+        Some(self.first + i as isize)
+    }
+
+    /// Get the number of lines after the line with the given ID that repeat it.
+    fn run_len(&self, id: isize) -> usize {
+        let i = (id - self.first) as usize;
+        self.repeats.iter().skip(i + 1).take_while(|&&r| r).count()
+    }
+
+    /// Check whether the line with the given ID is hidden in a collapsed group or run of repeats.
+    fn is_collapsed(&self, id: isize) -> bool {
+        (!self.expand_groups && self.head(id).is_some())
+            || (self.collapse_repeats && self.repeats[(id - self.first) as usize])
+    }
+
+    /// Remember the player names in a new line for completion.
+    fn learn(&mut self, line: &Line) {
+        if let Line::Log(s, _) = line {
+            if let Some(log) = self.config.log_formats.parse(s) {
+                self.completer.learn(log.message);
+                if let Some(name) = chat::joined_name(log.message) {
+                    self.players_seen.insert(name.to_string());
+                }
+            }
+        }
+    }
+
+    /// Get the ID of the bottom line on the screen, or of the line above the first line in the
+    /// buffer if no lines are visible.
+    fn bottom_id(&self) -> isize {
+        let h = self.log_height();
+        let bottom = (self.scroll + h).min(self.view.len() as isize);
+        if bottom > 0 {
+            self.view[bottom as usize - 1].id
+        } else {
+            self.first - 1
+        }
+    }
+
+    /// Add an old line to the front of the buffer. Returns the number of rows added to the view,
+    /// which can be negative if the line starts a group or run that collapses lines that were
+    /// shown.
+    fn push_front(&mut self, line: Line) -> isize {
+        self.learn(&line);
+        let continues = self.is_continuation(&line);
+        if let Some(next) = self.buffer.front() {
+            let repeat = !continues && self.is_repeat(next, &line);
+            self.repeats[0] = repeat;
+        }
+        self.buffer.push_front(line);
+        self.continues.push_front(continues);
+        self.repeats.push_front(false);
+        self.first -= 1;
+        if continues {
+            self.heads.push_front(isize::MIN);
+        } else {
+            self.heads.push_front(self.first);
+            // The lines that continue this one did not know their head until now.
+            for i in 1..self.buffer.len() {
+                if !self.continues[i] || self.heads[i] >= self.first {
+                    break;
+                }
+                self.heads[i] = self.first;
+            }
+        }
+
+        // Old logs come newest first, so the lines that continue or repeat this one are already
+        // in the buffer, and are shown as if they were not in a group. Show them again with the
+        // group.
+        // A line that continues another one does not change how the lines after it are shown.
+        let len = if continues {
+            0
+        } else {
+            self.group_len(self.first).max(self.run_len(self.first))
+        };
+        let end = self.first + len as isize;
+        let mut added = 0;
+        while matches!(self.view.front(), Some(row) if row.id <= end) {
+            self.view.pop_front();
+            added -= 1;
+        }
+        for id in (self.first..=end).rev() {
+            if self.is_visible(id) {
+                for row in self.rows(id).into_iter().rev() {
+                    self.view.push_front(row);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    /// Add a new line to the back of the buffer. Returns the number of rows added to the view.
+    fn push_back(&mut self, line: Line) -> isize {
+        self.learn(&line);
+        let continues = self.is_continuation(&line);
+        let repeat = match self.buffer.back() {
+            Some(prev) => !continues && self.is_repeat(&line, prev),
+            None => false,
+        };
+        let head = match self.heads.back() {
+            Some(&head) if continues => head,
+            None if continues => isize::MIN,
+            _ => self.first + self.buffer.len() as isize,
+        };
+        self.buffer.push_back(line);
+        self.continues.push_back(continues);
+        self.heads.push_back(head);
+        self.repeats.push_back(repeat);
+        // The summary of a collapsed group or run changes when a line is added to it.
+        self.show_last_group()
+    }
+
+    /// Show the rows of the lines in the last group or run again, after the number of lines in
+    /// it changed. Returns the number of rows added.
+    fn show_last_group(&mut self) -> isize {
+        let id = self.first + self.buffer.len() as isize - 1;
+        let start = match (self.head(id), self.run_head(id)) {
+            (Some(head), _) if !self.expand_groups => head,
+            (_, Some(head)) if self.collapse_repeats => head,
+            _ => id,
+        };
+        let mut added = 0;
+        while matches!(self.view.back(), Some(row) if row.id >= start) {
+            self.view.pop_back();
+            added -= 1;
+        }
+        for id in start..=id {
+            if self.is_visible(id) {
+                let rows = self.rows(id);
+                added += rows.len() as isize;
+                self.view.extend(rows);
+            }
+        }
+        added
+    }
+
+    /// Split the line with the given ID into rows. Lines in collapsed groups and runs have no rows.
+    fn rows(&self, id: isize) -> Vec<Row> {
+        if self.is_collapsed(id) {
+            return Vec::new();
+        }
+        let line = &self.buffer[(id - self.first) as usize];
+        let starts = match line {
+            // Headers are cut to the width of the screen instead.
+            Line::Header(_) => vec![0],
+            _ if !self.wrap => vec![0],
+            _ => wrap(
+                &self.display_text(id),
+                self.log_width().max(1) as usize,
+                self.wrap_indent(line),
+            ),
+        };
+        starts.into_iter().map(|start| Row { id, start }).collect()
+    }
+
+    /// Get the indent of the rows after the first row of a wrapped line. Logs are indented past
+    /// the time and level.
+    fn wrap_indent(&self, line: &Line) -> usize {
+        let prefix = match line {
+            Line::Log(s, file) => match self.config.log_formats.parse(s) {
+                Some(log) if self.chat && self.chat_name(line).is_some() => {
+                    let date = self
+                        .log_date(&log, file)
+                        .map_or(0, |t| t.text.chars().count());
+                    date + log.time.chars().count() + 1
+                }
+                Some(log) => self
+                    .log_prefix(&log, file)
+                    .iter()
+                    .map(|t| t.text.chars().count())
+                    .sum(),
+                None => 0,
+            },
+            _ => 0,
+        };
+        // Leave at least half of the screen for the text.
+        (prefix + self.config.wrap_indent).min(self.log_width().max(0) as usize / 2)
+    }
+
+    /// Get the width of the logs, which leave room for the player panel when it is shown.
+    fn log_width(&self) -> isize {
+        if self.show_players {
+            (self.width - self.config.player_panel_width as isize).max(0)
+        } else {
+            self.width
+        }
+    }
+
+    /// Get the number of rows of logs, which leave room for the date header when it is shown.
+    fn log_height(&self) -> isize {
+        if self.config.date_header {
+            self.height - 3
+        } else {
+            self.height - 2
         }
-        // Tell the input thread to keep going.
-        self.send_i.send(()).unwrap();
     }
 
     /// Get the maximum value for `scroll`.
     fn max_scroll(&self) -> isize {
-        let h = self.height - 2;
-        let l = self.buffer.len() as isize;
+        let h = self.log_height();
+        let l = self.view.len() as isize;
         if l < h {
             0
         } else {
@@ -152,12 +1604,12 @@ impl Console {
     }
 
     /// Add `delta` to `self.scroll` and redraw the logs. Fetches more old logs if necessary.
-    fn scroll(&mut self, recv_h: &Receiver<Line>, delta: isize) {
+    fn scroll(&mut self, recv_h: &Receiver<Option<Line>>, delta: isize) {
         if delta == 0 {
             return;
         }
         if delta > 0 {
-            let delta = delta;
+            while self.scroll + delta > self.max_scroll() && self.fetch_newer().is_some() {}
             let max_scroll = self.max_scroll();
             if self.scroll + delta > max_scroll {
                 self.scroll = max_scroll;
@@ -167,14 +1619,12 @@ impl Console {
         } else {
             let delta = -delta;
             if delta > self.scroll {
-                let to_fetch = delta - self.scroll;
-                for _ in 0..to_fetch {
-                    let log = recv_h.recv();
-                    if let Ok(log) = log {
-                        self.buffer.push_front(log);
-                    } else {
+                let mut to_fetch = delta - self.scroll;
+                while to_fetch > 0 {
+                    match self.fetch_older(recv_h) {
+                        Some(added) => to_fetch -= added,
                         // No more logs
-                        break;
+                        None => break,
                     }
                 }
                 self.scroll = 0;
@@ -191,7 +1641,6 @@ impl Console {
             return;
         }
         if delta > 0 {
-            let delta = delta;
             self.hscroll += delta;
         } else {
             let delta = -delta;
@@ -207,7 +1656,14 @@ impl Console {
     }
 
     /// Scroll to the end of the logs, and redraw the logs.
-    fn scroll_to_end(&mut self) {
+    fn scroll_to_end(&mut self, recv_h: &Receiver<Option<Line>>) {
+        if self.spilled_newer > 0 {
+            // Skip the lines in between instead of reading them all.
+            let last = self.first + self.buffer.len() as isize + self.spilled_newer - 1;
+            self.reload_at(recv_h, last);
+            self.draw_logs();
+            return;
+        }
         let max_scroll = self.max_scroll();
         if self.scroll != max_scroll {
             self.scroll = max_scroll;
@@ -231,13 +1687,13 @@ impl Console {
     fn print_line(&self, mut x: isize, y: isize, texts: Vec<Text>) {
         let left = x < 0;
         for text in texts {
-            let s: &str = &*text.text;
+            let s: &str = &text.text;
             let len = s.chars().count() as isize;
             self.print(x, y, s, text.color);
             x += len;
         }
 
-        let remaining = self.width - x;
+        let remaining = self.log_width() - x;
         let right = remaining < 0;
         if remaining > 0 {
             let spaces = " ".repeat(remaining as usize);
@@ -250,7 +1706,7 @@ impl Console {
         if right {
             let len = self.config.trun_right.chars().count() as isize;
             self.print(
-                self.width - len,
+                self.log_width() - len,
                 y,
                 &self.config.trun_right,
                 self.config.colors.truncate,
@@ -265,84 +1721,564 @@ impl Console {
     }
 
     fn draw_logs(&mut self) {
-        for i in 0..(self.height - 2) {
-            if let Some(msg) = self.buffer.get((i + self.scroll) as usize) {
+        let top = self.height - 2 - self.log_height();
+        if top > 0 {
+            self.draw_date_header();
+        }
+        for i in 0..self.log_height() {
+            let pos = (i + self.scroll) as usize;
+            let y = top + i;
+            let row = match self.view.get(pos) {
+                Some(&row) => row,
+                None => {
+                    self.print_line(0, y, Vec::new());
+                    continue;
+                }
+            };
+            if let Some(msg) = self.buffer.get((row.id - self.first) as usize) {
+                let mut texts = self.format_entry(row.id);
+                if let Some(regex) = self.search.as_ref().and_then(|s| s.regex.as_ref()) {
+                    texts = highlight(texts, regex, self.config.colors.search);
+                }
                 match msg {
-                    Line::Log(s) => {
-                        let texts = self.format_log(s);
-                        self.print_line(-(self.hscroll as isize), i, texts);
+                    // For headers, ignore horizontal scroll.
+                    Line::Header(_) => self.print_line(0, y, texts),
+                    _ if self.wrap => {
+                        let text = texts.iter().map(|t| &*t.text).collect::<String>();
+                        let end = match self.view.get(pos + 1) {
+                            Some(next) if next.id == row.id => next.start,
+                            _ => text.len(),
+                        };
+                        // Leave out the spaces where the line is broken.
+                        let end = row.start + text[row.start..end].trim_end_matches(' ').len();
+                        let mut texts = slice_texts(texts, row.start..end);
+                        if row.start > 0 {
+                            // Indent the rows after the first one.
+                            let indent = self.wrap_indent(msg);
+                            texts.insert(0, Text::normal(" ".repeat(indent).into()));
+                        }
+                        self.print_line(0, y, texts);
                     }
-                    Line::Header(s) => {
-                        // For headers, ignore horizontal scroll.
-                        let width = (self.width - 6) as usize;
-                        let output = format!(" --> {:.*}", width, s);
-                        let texts = vec![Text::new(output.into(), self.config.colors.file_header)];
-                        self.print_line(0, i, texts);
+                    _ => self.print_line(-self.hscroll, y, texts),
+                }
+            }
+        }
+        self.draw_players();
+        self.draw_completion();
+        // The scroll position may be shown in the status bar.
+        self.draw_status();
+    }
+
+    /// Draw the date and file of the topmost log on the screen above the logs, so that it is
+    /// known which day the logs are from while scrolling.
+    fn draw_date_header(&self) {
+        let file = self
+            .view
+            .iter()
+            .skip(self.scroll.max(0) as usize)
+            .take(self.log_height().max(0) as usize)
+            .find_map(
+                |row| match self.buffer.get((row.id - self.first) as usize) {
+                    Some(Line::Log(_, file)) => Some(file),
+                    _ => None,
+                },
+            );
+        let mut text = String::new();
+        if let Some(file) = file {
+            if let Some(date) = file.date {
+                text += &format!(" {}", date);
+            }
+            if let Some(path) = &file.path {
+                text += &format!("  {}", path);
+            }
+        }
+        let width = self.log_width().max(0) as usize;
+        let text = format!("{:w$.w$}", text, w = width);
+        self.print(0, 0, &text, self.config.colors.file_header);
+    }
+
+    /// Draw the panel listing the players online to the right of the logs, with how long they have
+    /// been online, and their IP address and position when they logged in.
+    fn draw_players(&self) {
+        if !self.show_players {
+            return;
+        }
+        let x = self.log_width();
+        let width = (self.width - x - 2).max(0) as usize;
+        let colors = &self.config.colors;
+        let players = self.server.players();
+        let count = match players {
+            Some(players) => players.len().to_string(),
+            None => "?".to_string(),
+        };
+        let max = match self.server.max_players() {
+            Some(max) => max.to_string(),
+            None => "?".to_string(),
+        };
+        let mut rows = vec![(format!("Players {}/{}", count, max), colors.panel_title)];
+        match players {
+            Some(players) => {
+                for (name, player) in players {
+                    let online = format_duration(player.joined.elapsed());
+                    rows.push((format!("{} {}", name, online), colors.panel));
+                    if let Some(ip) = &player.ip {
+                        rows.push((format!("  {}", ip), colors.panel));
+                    }
+                    if let Some(position) = &player.position {
+                        rows.push((format!("  {}", position), colors.panel));
                     }
                 }
             }
+            None => rows.push(("not known yet; try /list".to_string(), colors.panel)),
+        }
+        for y in 0..(self.height - 2) {
+            let (text, color) = match rows.get(y as usize) {
+                Some((text, color)) => (&**text, *color),
+                None => ("", colors.panel),
+            };
+            self.print(x, y, "│ ", colors.panel);
+            self.print(x + 2, y, &format!("{:w$.w$}", text, w = width), color);
         }
     }
 
-    fn format_log<'a>(&self, log: &'a str) -> Vec<Text<'a>> {
-        lazy_static! {
-            static ref REGEX: Regex =
-                Regex::new(r"^\[(\d\d:\d\d:\d\d)] \[([^]/]+)/([A-Z]+)](?: \[[^]]*])*: (.*)$")
-                    .unwrap();
+    /// Draw the completion candidates in a popup above the prompt.
+    fn draw_completion(&self) {
+        let completion = match &self.completion {
+            Some(completion) => completion,
+            None => return,
+        };
+        let candidates = &completion.candidates;
+        let rows = (candidates.len() as isize)
+            .min(POPUP_ROWS)
+            .min(self.height - 2);
+        // Scroll the candidates so that the selected one is visible.
+        let selected = completion.selected.unwrap_or(0) as isize;
+        let first = (selected - rows + 1).max(0);
+        let width = candidates
+            .iter()
+            .map(|c| c.chars().count())
+            .max()
+            .unwrap_or(0);
+        let x = (self.input.column(completion.start) as isize + 2)
+            .min(self.width - width as isize - 2)
+            .max(0);
+        for row in 0..rows {
+            let i = (first + row) as usize;
+            let color = if completion.selected == Some(i) {
+                self.config.colors.popup_selected
+            } else {
+                self.config.colors.popup
+            };
+            let text = format!(" {:w$} ", candidates[i], w = width);
+            self.print(x, self.height - 2 - rows + row, &text, color);
         }
-        if let Some(cap) = REGEX.captures(log) {
-            vec![
-                Text::new(cap[1].to_string().into(), self.config.colors.time),
-                Text::normal(" ".into()),
-                Text::new(
-                    cap[3].to_string().into(),
-                    match &cap[3] {
-                        "INFO" => self.config.colors.info,
-                        "WARN" => self.config.colors.warn,
-                        "ERROR" => self.config.colors.error,
-                        "SEVERE" => self.config.colors.severe,
-                        "FATAL" => self.config.colors.fatal,
-                        _ => self.config.colors.other,
+    }
+
+    fn format_line<'a>(&self, line: &'a Line) -> Vec<Text<'a>> {
+        match line {
+            Line::Log(s, file) => self.format_log(s, file),
+            Line::Response(s) => self.styled(s, self.config.colors.response),
+            Line::Notice(s) => vec![Text::new(s.into(), self.config.colors.notice)],
+            Line::Header(s) => {
+                let width = (self.log_width() - 6).max(0) as usize;
+                let output = format!(" --> {:.*}", width, s);
+                vec![Text::new(output.into(), self.config.colors.file_header)]
+            }
+        }
+    }
+
+    /// Format the line with the given ID. Lines in a group that are not in a log format get the
+    /// color of the level of the first line, and collapsed groups are summarized.
+    fn format_entry(&self, id: isize) -> Vec<Text<'_>> {
+        let line = &self.buffer[(id - self.first) as usize];
+        if let (Some(head), Line::Log(s, _)) = (self.head(id), line) {
+            if self.config.log_formats.parse(s).is_none() {
+                let color = match &self.buffer[(head - self.first) as usize] {
+                    Line::Log(head, _) => match self.config.log_formats.parse(head) {
+                        Some(log) if !log.level.is_empty() => self.level_color(log.level),
+                        _ => self.config.colors.text,
                     },
-                ),
-                Text::normal(": ".into()),
-                Text::new(cap[4].to_string().into(), self.config.colors.text),
-            ]
+                    _ => self.config.colors.text,
+                };
+                return self.format_message(s, color);
+            }
+        }
+
+        let mut texts = self.format_line(line);
+        let hidden = if self.expand_groups || self.continues[(id - self.first) as usize] {
+            0
+        } else {
+            self.group_len(id)
+        };
+        if hidden > 0 {
+            texts.push(Text::normal(" ".into()));
+            let summary = format!("+{} lines", hidden);
+            texts.push(Text::new(summary.into(), self.config.colors.collapsed));
+        }
+        let repeated = if self.collapse_repeats {
+            self.run_len(id)
+        } else {
+            0
+        };
+        if repeated > 0 {
+            texts.push(Text::normal(" ".into()));
+            let count = format!("×{}", repeated + 1);
+            texts.push(Text::new(count.into(), self.config.colors.collapsed));
+        }
+        texts
+    }
+
+    /// Get the text of the line with the given ID as it appears on the screen.
+    fn display_text(&self, id: isize) -> String {
+        self.format_entry(id)
+            .iter()
+            .map(|t| &*t.text)
+            .collect::<String>()
+    }
+
+    fn format_log<'a>(&self, log: &'a str, file: &LogFile) -> Vec<Text<'a>> {
+        if let Some(log) = self.config.log_formats.parse(log) {
+            if self.chat {
+                if let Some(name) = chat::find_name(log.message, &self.players_seen) {
+                    return self.format_chat(&log, file, name);
+                }
+            }
+            let mut texts = self.log_prefix(&log, file);
+            texts.extend(self.format_message(log.message, self.config.colors.text));
+            texts
         } else {
-            vec![Text::new(log.into(), self.config.colors.text)]
+            self.format_message(log, self.config.colors.text)
+        }
+    }
+
+    /// Format a chat log in the chat view, with only the time before the message and the name of
+    /// the player in its own color.
+    fn format_chat<'a>(&self, log: &Log<'a>, file: &LogFile, name: Range<usize>) -> Vec<Text<'a>> {
+        let mut texts: Vec<_> = self.log_date(log, file).into_iter().collect();
+        if !log.time.is_empty() {
+            texts.push(Text::new(log.time.into(), self.config.colors.time));
+            texts.push(Text::normal(" ".into()));
         }
+        let message = log.message;
+        let color = chat::name_color(&message[name.clone()]);
+        texts.extend(self.format_message(&message[..name.start], self.config.colors.text));
+        texts.push(Text::new(message[name.clone()].into(), color));
+        texts.extend(self.format_message(&message[name.end..], self.config.colors.text));
+        texts
+    }
+
+    /// Format the message of a log, with the highlight rules applied.
+    fn format_message<'a>(&self, message: &'a str, color: Color) -> Vec<Text<'a>> {
+        let mut texts = self.styled(message, color);
+        // The rules are in order of priority, so the ones with higher priority are applied last
+        // and replace the others.
+        for rule in &self.config.highlight {
+            texts = highlight(texts, &rule.regex, rule.color);
+        }
+        texts
+    }
+
+    /// Format the date, time and level shown before the message of a log.
+    fn log_prefix<'a>(&self, log: &Log<'a>, file: &LogFile) -> Vec<Text<'a>> {
+        let mut texts: Vec<_> = self.log_date(log, file).into_iter().collect();
+        if !log.time.is_empty() {
+            texts.push(Text::new(log.time.into(), self.config.colors.time));
+        }
+        if !log.level.is_empty() {
+            if !texts.is_empty() {
+                texts.push(Text::normal(" ".into()));
+            }
+            texts.push(Text::new(log.level.into(), self.level_color(log.level)));
+        }
+        if !texts.is_empty() {
+            texts.push(Text::normal(": ".into()));
+        }
+        texts
+    }
+
+    /// Format the date shown before the time of a log if dates are shown, which is blank if the
+    /// date of its file is not known so that the times still line up.
+    fn log_date<'a>(&self, log: &Log, file: &LogFile) -> Option<Text<'a>> {
+        if !self.config.show_dates || log.time.is_empty() {
+            return None;
+        }
+        let date = match file.date {
+            Some(date) => format!("{} ", date),
+            None => " ".repeat(11),
+        };
+        Some(Text::new(date.into(), self.config.colors.time))
+    }
+
+    fn level_color(&self, level: &str) -> Color {
+        match level {
+            "INFO" => self.config.colors.info,
+            "WARN" | "WARNING" => self.config.colors.warn,
+            "ERROR" => self.config.colors.error,
+            "SEVERE" => self.config.colors.severe,
+            "FATAL" => self.config.colors.fatal,
+            _ => self.config.colors.other,
+        }
+    }
+
+    /// Split `text` into parts styled by the formatting codes in it, or remove the codes if they
+    /// are not rendered.
+    fn styled<'a>(&self, text: &'a str, color: Color) -> Vec<Text<'a>> {
+        let render = self.config.format_codes == FormatCodes::Render;
+        style::parse(text, color, render)
+            .into_iter()
+            .map(|(s, color)| Text::new(s.into(), color))
+            .collect()
     }
 
     fn draw_input(&mut self) {
-        let width = (self.width - 4) as usize;
-        let output = format!(" > {:0$.*} ", width, self.input);
+        let width = (self.width - 4).max(0) as usize;
+        if self.prompt == Prompt::Recall {
+            let query = self.recall_query.text();
+            let found = self.recall_found.and_then(|i| self.commands.get(i));
+            let failed = found.is_none() && !query.is_empty();
+            let before = format!(
+                "({}reverse-i-search)`{}",
+                if failed { "failed " } else { "" },
+                query
+            );
+            let text = format!("{}': {}", before, found.unwrap_or_default());
+            let output = format!(" ? {:w$.w$} ", text, w = width);
+            self.print(0, self.height - 2, &output, self.config.colors.prompt);
+            let cursor = (before.chars().count() as isize).min(width as isize);
+            self.rb.set_cursor(cursor + 3, self.height - 2);
+            return;
+        }
+
+        let (prompt, editor) = match (self.prompt, &mut self.search) {
+            (Prompt::Search, Some(search)) => ('/', &mut search.query),
+            (Prompt::Filter, _) => ('|', &mut self.filter_input),
+            (Prompt::Goto, _) => ('@', &mut self.goto_input),
+            _ => ('>', &mut self.input),
+        };
+        let (text, cursor) = editor.visible(width);
+        let output = format!(" {} {:w$.w$} ", prompt, text, w = width);
         self.print(0, self.height - 2, &output, self.config.colors.prompt);
-        self.rb.set_cursor(
-            self.input.chars().count() as isize + 3,
-            self.height as isize - 2,
-        )
+        if self.focus == Focus::Logs && self.prompt == Prompt::Command {
+            // Hide the cursor to show that the arrow keys scroll the logs.
+            self.rb.set_cursor(-1, -1);
+        } else {
+            self.rb.set_cursor(cursor as isize + 3, self.height - 2);
+        }
     }
 
     fn draw_status(&mut self) {
         let width = (self.width - 2) as usize;
-        let output = format!(" {:0$.*} ", width, self.config.default_status);
+        if let Some((text, _)) = &self.flash {
+            let output = format!(" {:0$.*} ", width, text);
+            self.print(0, self.height - 1, &output, self.config.colors.alert);
+            return;
+        }
+        let status = match &self.message {
+            Some(message) => message.clone(),
+            None => {
+                let scrolled = self.max_scroll() - self.scroll + self.spilled_newer;
+                let keys = &self.config.keys;
+                self.config
+                    .default_status
+                    .render(&self.server, keys, scrolled)
+            }
+        };
+        let status = match &self.filter {
+            Some(filter) => format!("[filter: {}] {}", filter.text, status),
+            None => status,
+        };
+        let status = if self.chat {
+            format!("[chat] {}", status)
+        } else {
+            status
+        };
+        let output = format!(" {:0$.*} ", width, status);
         self.print(0, self.height - 1, &output, self.config.colors.status);
     }
 }
 
+/// Format how long a player has been online, like `1h05m`.
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+/// Find where to break `text` into rows of at most `width` characters, with the rows after the
+/// first one indented by `indent`. Lines are broken after spaces where possible, and the spaces at
+/// the start of the rows after the first one are skipped. Returns the byte index where each row
+/// starts.
+fn wrap(text: &str, width: usize, indent: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut start = 0;
+    let mut room = width;
+    loop {
+        // Byte index of the first character that does not fit
+        let end = match text[start..].char_indices().nth(room) {
+            Some((i, _)) => start + i,
+            None => return starts,
+        };
+        start = if text[end..].starts_with(' ') {
+            end
+        } else {
+            match text[start..end].rfind(' ') {
+                Some(i) if !text[start..start + i].trim().is_empty() => start + i + 1,
+                // A single word is longer than the row.
+                _ => end,
+            }
+        };
+        start += text[start..].len() - text[start..].trim_start_matches(' ').len();
+        if start == text.len() {
+            return starts;
+        }
+        starts.push(start);
+        room = (width - indent).max(1);
+    }
+}
+
+/// Get the parts of `texts` that are in the byte range `range` of their concatenated text.
+fn slice_texts<'a>(texts: Vec<Text<'a>>, range: Range<usize>) -> Vec<Text<'a>> {
+    let mut pos = 0;
+    let mut output = Vec::new();
+    for text in texts {
+        let len = text.text.len();
+        let start = range.start.max(pos) - pos;
+        let end = range.end.min(pos + len).saturating_sub(pos);
+        pos += len;
+        if start >= end {
+            continue;
+        }
+        let part = match text.text {
+            Cow::Borrowed(s) => Cow::Borrowed(&s[start..end]),
+            Cow::Owned(s) => Cow::Owned(s[start..end].to_string()),
+        };
+        output.push(Text::new(part, text.color));
+    }
+    output
+}
+
+/// Get the longest prefix that `strings` have in common, ignoring case, as it is in the first
+/// string.
+fn common_prefix(strings: &[String]) -> &str {
+    let first = match strings.first() {
+        Some(s) => s,
+        None => return "",
+    };
+    let mut len = first.len();
+    for s in &strings[1..] {
+        let same = first
+            .char_indices()
+            .zip(s.chars())
+            .take_while(|((_, a), b)| a.to_lowercase().eq(b.to_lowercase()))
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8());
+        len = len.min(same);
+    }
+    &first[..len]
+}
+
+/// Get the common prefix of the candidates to complete `word` with, or `None` if it is not longer
+/// than the word, so that the word is never replaced with less than what was typed.
+fn extended_prefix<'a>(word: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let prefix = common_prefix(candidates);
+    if prefix.chars().count() > word.chars().count() {
+        Some(prefix)
+    } else {
+        None
+    }
+}
+
+/// Split `texts` so that the parts matching `regex` are shown with `color`.
+fn highlight<'a>(texts: Vec<Text<'a>>, regex: &Regex, color: Color) -> Vec<Text<'a>> {
+    let full = texts.iter().map(|t| &*t.text).collect::<String>();
+    let ranges = regex
+        .find_iter(&full)
+        .map(|m| m.range())
+        .filter(|r| !r.is_empty())
+        .collect::<Vec<Range<usize>>>();
+    if ranges.is_empty() {
+        return texts;
+    }
+
+    let mut output = Vec::new();
+    let mut start = 0;
+    for text in texts {
+        let end = start + text.text.len();
+        let mut pos = start;
+        for r in ranges.iter().filter(|r| r.start < end && r.end > start) {
+            let (s, e) = (r.start.max(start), r.end.min(end));
+            if s > pos {
+                let part = text.text[pos - start..s - start].to_string();
+                output.push(Text::new(part.into(), text.color));
+            }
+            let part = text.text[s - start..e - start].to_string();
+            output.push(Text::new(part.into(), color));
+            pos = e;
+        }
+        if pos == start {
+            output.push(text);
+        } else if pos < end {
+            let part = text.text[pos - start..].to_string();
+            output.push(Text::new(part.into(), text.color));
+        }
+        start = end;
+    }
+    output
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     config: Arc<Config>,
     rustbox: Arc<RustBox>,
-    recv_h: Receiver<Line>,
+    recv_h: Receiver<Option<Line>>,
+    recv_s: Receiver<ServerState>,
     recv_l: Receiver<Line>,
-    recv_i: Receiver<Event>,
+    recv_i: Receiver<Input>,
     send_c: Sender<String>,
+    send_g: Sender<Goto>,
     send_i: Sender<()>,
+    commands: CommandHistory,
+    completer: Completer,
 ) {
     Console {
+        alerted: vec![None; config.alert.len()],
+        wrap: config.wrap,
+        show_players: config.show_players,
+        collapse_repeats: config.collapse_repeats,
+        journal: Journal::new(config.spill_dir()),
         config,
         buffer: VecDeque::new(),
-        input: String::new(),
+        first: 0,
+        spilled_newer: 0,
+        history_end: false,
+        continues: VecDeque::new(),
+        heads: VecDeque::new(),
+        expand_groups: false,
+        repeats: VecDeque::new(),
+        chat: false,
+        other_bottom: None,
+        players_seen: HashSet::new(),
+        view: VecDeque::new(),
+        filter: None,
+        input: Editor::default(),
+        search: None,
+        filter_input: Editor::default(),
+        goto_input: Editor::default(),
+        prompt: Prompt::Command,
+        focus: Focus::Prompt,
+        commands,
+        recall: None,
+        draft: String::new(),
+        recall_query: Editor::default(),
+        recall_found: None,
+        completer,
+        completion: None,
+        message: None,
+        server: ServerState::new(),
+        flash: None,
         scroll: 0,
         hscroll: 0,
         height: 0,
@@ -350,7 +2286,141 @@ pub fn run(
         exit: false,
         rb: rustbox,
         send_c,
+        send_g,
         send_i,
     }
-    .mainloop(recv_h, recv_l, recv_i);
+    .mainloop(recv_h, recv_s, recv_l, recv_i);
+}
+
+#[cfg(test)]
+mod tests {
+    use rustbox::{Color as RbColor, RB_NORMAL};
+
+    use super::*;
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Break `text` into rows with `wrap`.
+    fn rows(text: &str, width: usize, indent: usize) -> Vec<&str> {
+        let mut starts = wrap(text, width, indent);
+        starts.push(text.len());
+        starts.windows(2).map(|w| &text[w[0]..w[1]]).collect()
+    }
+
+    #[test]
+    fn wrap_at_spaces() {
+        assert_eq!(rows("", 10, 0), [""]);
+        assert_eq!(rows("hello world", 11, 0), ["hello world"]);
+        assert_eq!(rows("hello world foo", 11, 0), ["hello world ", "foo"]);
+        assert_eq!(rows("hello world foo", 8, 0), ["hello ", "world ", "foo"]);
+        // The spaces at the start of the rows after the first one are skipped.
+        assert_eq!(rows("hello    world", 7, 0), ["hello    ", "world"]);
+    }
+
+    #[test]
+    fn wrap_long_words() {
+        assert_eq!(rows("abcdefghij", 4, 0), ["abcd", "efgh", "ij"]);
+        assert_eq!(rows("ab cdefghij", 4, 0), ["ab ", "cdef", "ghij"]);
+        // Spaces at the start of the line are not a place to break.
+        assert_eq!(rows("    abcdef", 4, 0), ["    ", "abcd", "ef"]);
+    }
+
+    #[test]
+    fn wrap_indent() {
+        assert_eq!(rows("aaaa bbbb cccc", 6, 2), ["aaaa ", "bbbb ", "cccc"]);
+        assert_eq!(rows("abcdefghij", 6, 2), ["abcdef", "ghij"]);
+        assert_eq!(
+            rows("abcdefghij", 4, 4),
+            ["abcd", "e", "f", "g", "h", "i", "j"]
+        );
+    }
+
+    #[test]
+    fn wrap_multibyte() {
+        assert_eq!(rows("héllo wörld", 5, 0), ["héllo ", "wörld"]);
+        assert_eq!(rows("日本語の文章", 2, 0), ["日本", "語の", "文章"]);
+    }
+
+    #[test]
+    fn wrap_trailing_spaces() {
+        assert_eq!(rows("abc   ", 3, 0), ["abc   "]);
+        assert_eq!(rows("abc def   ", 4, 0), ["abc ", "def   "]);
+    }
+
+    #[test]
+    fn common_prefix_ignores_case() {
+        assert_eq!(common_prefix(&strings(&["Steve", "stone"])), "St");
+        assert_eq!(common_prefix(&strings(&["Notch", "NOTE"])), "Not");
+        assert_eq!(common_prefix(&strings(&["kill", "kick", "Alex"])), "");
+        assert_eq!(common_prefix(&strings(&["ÉTÉ", "été"])), "ÉTÉ");
+    }
+
+    #[test]
+    fn completion_never_shortens_the_word() {
+        let candidates = strings(&["Steve", "stone"]);
+        assert_eq!(extended_prefix("st", &candidates), None);
+        assert_eq!(extended_prefix("s", &candidates), Some("St"));
+        assert_eq!(
+            extended_prefix("", &strings(&["Steve", "Stone"])),
+            Some("St")
+        );
+        assert_eq!(extended_prefix("x", &strings(&["Alex", "Steve"])), None);
+    }
+
+    /// Get the text and foreground color of each part of `texts`.
+    fn parts(texts: Vec<Text>) -> Vec<(String, RbColor)> {
+        texts
+            .into_iter()
+            .map(|t| (t.text.into_owned(), t.color.fg))
+            .collect()
+    }
+
+    #[test]
+    fn highlight_overlapping_rules() {
+        let texts = vec![Text::normal("hello world".into())];
+        let texts = highlight(
+            texts,
+            &Regex::new("hello wo").unwrap(),
+            Color::new(1, 0, RB_NORMAL),
+        );
+        let texts = highlight(
+            texts,
+            &Regex::new("o w").unwrap(),
+            Color::new(2, 0, RB_NORMAL),
+        );
+        assert_eq!(
+            parts(texts),
+            vec![
+                ("hell".to_string(), RbColor::Byte(1)),
+                ("o w".to_string(), RbColor::Byte(2)),
+                ("o".to_string(), RbColor::Byte(1)),
+                ("rld".to_string(), RbColor::Byte(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlight_across_parts() {
+        let texts = vec![
+            Text::new("12:00".into(), Color::new(3, 0, RB_NORMAL)),
+            Text::normal(" said hi".into()),
+        ];
+        let texts = highlight(
+            texts,
+            &Regex::new(r"00 s|hi").unwrap(),
+            Color::new(4, 0, RB_NORMAL),
+        );
+        assert_eq!(
+            parts(texts),
+            vec![
+                ("12:".to_string(), RbColor::Byte(3)),
+                ("00".to_string(), RbColor::Byte(4)),
+                (" s".to_string(), RbColor::Byte(4)),
+                ("aid ".to_string(), RbColor::Byte(0)),
+                ("hi".to_string(), RbColor::Byte(4)),
+            ]
+        );
+    }
 }
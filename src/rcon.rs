@@ -0,0 +1,195 @@
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Packet type for logging in.
+const LOGIN: i32 = 3;
+/// Packet type for running a command.
+const COMMAND: i32 = 2;
+/// Packet type of the responses to commands.
+const RESPONSE: i32 = 0;
+
+/// A connection to a server's RCON port.
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Rcon {
+    /// Connect to `addr` and log in with `password`. Connecting, and each read and write, fail if
+    /// the server does not answer within `timeout`.
+    pub fn connect(addr: &str, password: &str, timeout: Duration) -> io::Result<Rcon> {
+        let mut error = io::Error::new(ErrorKind::NotFound, "RCON address not found");
+        let mut stream = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) => error = e,
+            }
+        }
+        let stream = stream.ok_or_else(|| timed_out(error))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let mut rcon = Rcon { stream, next_id: 1 };
+        let id = rcon.send(LOGIN, password)?;
+        let (resp_id, _, _) = rcon.recv()?;
+        if resp_id != id {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "wrong RCON password",
+            ));
+        }
+        Ok(rcon)
+    }
+
+    /// Run a command and return its output.
+    pub fn command(&mut self, command: &str) -> io::Result<String> {
+        let id = self.send(COMMAND, command)?;
+        // Long responses are split into several packets, and there is no way to tell which one is
+        // the last. The server answers packets in order, so send another packet and collect the
+        // responses until its answer arrives.
+        let end = self.send(RESPONSE, "")?;
+        let mut output = String::new();
+        loop {
+            let (resp_id, _, body) = self.recv()?;
+            if resp_id == end {
+                return Ok(output);
+            } else if resp_id == id {
+                output.push_str(&body);
+            }
+        }
+    }
+
+    fn send(&mut self, kind: i32, body: &str) -> io::Result<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let len = body.len() as i32 + 10;
+        let mut packet = Vec::with_capacity(len as usize + 4);
+        packet.extend_from_slice(&len.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&packet).map_err(timed_out)?;
+        Ok(id)
+    }
+
+    /// Read a packet, and return its ID, type and body.
+    fn recv(&mut self) -> io::Result<(i32, i32, String)> {
+        let len = self.read_i32()?;
+        if !(10..=1024 * 1024).contains(&len) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "invalid RCON packet length",
+            ));
+        }
+        let id = self.read_i32()?;
+        let kind = self.read_i32()?;
+        let mut body = vec![0; len as usize - 8];
+        self.stream.read_exact(&mut body).map_err(timed_out)?;
+        // Remove the null bytes at the end.
+        while body.last() == Some(&0) {
+            body.pop();
+        }
+        Ok((id, kind, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        let mut buf = [0; 4];
+        self.stream.read_exact(&mut buf).map_err(timed_out)?;
+        Ok(i32::from_le_bytes(buf))
+    }
+}
+
+/// Replace the error of a timeout, which is not clear on some platforms, with one that says so.
+fn timed_out(e: io::Error) -> io::Error {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            io::Error::new(ErrorKind::TimedOut, "the server did not answer in time")
+        }
+        _ => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::thread::JoinHandle;
+
+    /// A mock server that answers each packet it reads with the packets returned by `answer`,
+    /// given the ID, type and body of the packet.
+    fn mock<F>(answer: F) -> (String, JoinHandle<()>)
+    where
+        F: Fn(i32, i32, &str) -> Vec<(i32, i32, String)> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut rcon = Rcon { stream, next_id: 1 };
+            while let Ok((id, kind, body)) = rcon.recv() {
+                for (id, kind, body) in answer(id, kind, &body) {
+                    rcon.next_id = id;
+                    rcon.send(kind, &body).unwrap();
+                }
+            }
+        });
+        (addr, handle)
+    }
+
+    /// Answer logins with `password`, and commands with their text split into packets of
+    /// `split` bytes.
+    fn server(password: &'static str, split: usize) -> (String, JoinHandle<()>) {
+        mock(move |id, kind, body| match kind {
+            LOGIN if body == password => vec![(id, 2, String::new())],
+            LOGIN => vec![(-1, 2, String::new())],
+            COMMAND => body
+                .as_bytes()
+                .chunks(split)
+                .map(|c| (id, RESPONSE, String::from_utf8(c.to_vec()).unwrap()))
+                .collect(),
+            _ => vec![(id, RESPONSE, "Unknown request 0".to_string())],
+        })
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn wrong_password() {
+        let (addr, _server) = server("secret", 100);
+        let e = Rcon::connect(&addr, "guess", TIMEOUT).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn command() {
+        let (addr, server) = server("secret", 100);
+        let mut rcon = Rcon::connect(&addr, "secret", TIMEOUT).unwrap();
+        assert_eq!(rcon.command("list").unwrap(), "list");
+        assert_eq!(rcon.command("say hi").unwrap(), "say hi");
+        drop(rcon);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn split_response() {
+        let (addr, _server) = server("secret", 4);
+        let mut rcon = Rcon::connect(&addr, "secret", TIMEOUT).unwrap();
+        let command = "help and a long response";
+        assert_eq!(rcon.command(command).unwrap(), command);
+    }
+
+    #[test]
+    fn no_answer() {
+        let (addr, _server) = mock(|_, _, _| Vec::new());
+        let timeout = Duration::from_millis(100);
+        let e = Rcon::connect(&addr, "secret", timeout).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+    }
+}
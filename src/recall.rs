@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+/// Commands previously entered by the user, saved to a file.
+pub struct CommandHistory {
+    entries: VecDeque<String>,
+    size: usize,
+    path: PathBuf,
+}
+
+impl CommandHistory {
+    /// Load the last `size` commands from the file at `path`.
+    pub fn load(path: PathBuf, size: usize) -> io::Result<CommandHistory> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let lines = text.lines().filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let skip = lines.len().saturating_sub(size);
+        let history = CommandHistory {
+            entries: lines[skip..].iter().map(|l| l.to_string()).collect(),
+            size,
+            path,
+        };
+        if skip > 0 {
+            // Keep the file from growing forever.
+            history.save()?;
+        }
+        Ok(history)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|s| s.as_str())
+    }
+
+    /// Add a command to the end of the history and append it to the file.
+    pub fn add(&mut self, command: &str) -> io::Result<()> {
+        if self.size == 0
+            || command.trim().is_empty()
+            || self.entries.back().map(|s| s.as_str()) == Some(command)
+        {
+            return Ok(());
+        }
+        self.entries.push_back(command.to_string());
+        while self.entries.len() > self.size {
+            self.entries.pop_front();
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", command)
+    }
+
+    /// Find the last command before `before` that contains `query`, and return its index.
+    pub fn find(&self, query: &str, before: usize) -> Option<usize> {
+        let before = before.min(self.entries.len());
+        self.entries
+            .range(..before)
+            .rposition(|entry| entry.contains(query))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(entry);
+            text.push('\n');
+        }
+        fs::write(&self.path, text)
+    }
+}
@@ -0,0 +1,178 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel;
+use crossbeam::channel::{select, Receiver, RecvTimeoutError, Sender};
+
+use crate::config::Server;
+use crate::output::{Line, LogFile};
+
+/// A running server process.
+struct Running {
+    child: Child,
+    stdin: ChildStdin,
+    started: Instant,
+    /// Disconnected once the server has closed both stdout and stderr.
+    done: Receiver<()>,
+}
+
+/// Run the server as a child process, forwarding its output as logs and sending commands to its
+/// stdin. When the input is disconnected, the server is stopped.
+pub fn start(
+    config: Server,
+    input: Receiver<String>,
+    output: Sender<Line>,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let notice = |msg: String| {
+            let _ = output.send(Line::Notice(msg));
+        };
+        let mut server = launch(&config, &output);
+        // Whether the user asked the server to stop, in which case it is not restarted.
+        let mut stopping = false;
+        let mut delay = Duration::from_secs(config.restart_delay);
+
+        loop {
+            let running = match &mut server {
+                Some(running) => running,
+                None => {
+                    // The server is not running, so wait for the user to start it.
+                    match input.recv() {
+                        Ok(command) if command.trim() == config.start_command => {
+                            stopping = false;
+                            server = launch(&config, &output);
+                        }
+                        Ok(_) => notice(format!(
+                            "server is not running; type '{}' to start it",
+                            config.start_command
+                        )),
+                        Err(_) => return Ok(()),
+                    }
+                    continue;
+                }
+            };
+
+            select! {
+                recv(input) -> command => match command {
+                    Ok(command) => {
+                        if command.trim() == config.stop_command {
+                            stopping = true;
+                        }
+                        if let Err(e) = writeln!(running.stdin, "{}", command)
+                            .and_then(|_| running.stdin.flush())
+                        {
+                            notice(format!("failed to send command: {}", e));
+                        }
+                    }
+                    Err(_) => {
+                        // The console is exiting, so stop the server. Ignore errors in case it
+                        // has already exited.
+                        let _ = writeln!(running.stdin, "{}", config.stop_command)
+                            .and_then(|_| running.stdin.flush());
+                        // The server closes its output when it exits.
+                        let timeout = Duration::from_secs(config.stop_timeout);
+                        if let Err(RecvTimeoutError::Timeout) = running.done.recv_timeout(timeout) {
+                            // The console has exited, so there is no scrollback to report this in.
+                            eprintln!(
+                                "server did not stop within {} s; killing it",
+                                config.stop_timeout
+                            );
+                            let _ = running.child.kill();
+                        }
+                        running.child.wait()?;
+                        return Ok(());
+                    }
+                },
+                recv(running.done) -> _ => {
+                    let status = running.child.wait()?;
+                    let uptime = running.started.elapsed();
+                    server = None;
+                    notice(format!("server exited ({})", status));
+                    if stopping || !config.auto_restart {
+                        continue;
+                    }
+
+                    // Restart with exponential backoff, unless the server ran for a while.
+                    let max_delay = Duration::from_secs(config.max_restart_delay);
+                    if uptime >= max_delay {
+                        delay = Duration::from_secs(config.restart_delay);
+                    }
+                    notice(format!("restarting server in {} s", delay.as_secs()));
+                    let deadline = Instant::now() + delay;
+                    loop {
+                        select! {
+                            recv(input) -> command => match command {
+                                Ok(_) => notice("server is restarting; command not sent".into()),
+                                Err(_) => return Ok(()),
+                            },
+                            default(deadline.saturating_duration_since(Instant::now())) => break,
+                        }
+                    }
+                    delay = (delay * 2).min(max_delay);
+                    server = launch(&config, &output);
+                }
+            }
+        }
+    })
+}
+
+/// Start the server, reporting errors in the logs.
+fn launch(config: &Server, output: &Sender<Line>) -> Option<Running> {
+    match spawn(config, output) {
+        Ok(running) => Some(running),
+        Err(e) => {
+            let _ = output.send(Line::Notice(format!("failed to start server: {}", e)));
+            None
+        }
+    }
+}
+
+fn spawn(config: &Server, output: &Sender<Line>) -> io::Result<Running> {
+    let (program, args) = config.command.split_first().unwrap();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let (send_done, done) = channel::bounded(0);
+    forward(
+        child.stdout.take().unwrap(),
+        output.clone(),
+        send_done.clone(),
+    );
+    forward(child.stderr.take().unwrap(), output.clone(), send_done);
+    Ok(Running {
+        stdin: child.stdin.take().unwrap(),
+        child,
+        started: Instant::now(),
+        done,
+    })
+}
+
+/// Send each line from `stream` to `output`, and drop `done` at the end of the stream.
+fn forward<R: Read + Send + 'static>(stream: R, output: Sender<Line>, done: Sender<()>) {
+    thread::spawn(move || {
+        let _done = done;
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        let mut file = LogFile::today(None);
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            // Keep reading even if the output thread has exited, so that the server does not
+            // block while writing.
+            LogFile::keep_today(&mut file);
+            let _ = output.send(Line::Log(line.replace('\t', "    "), file.clone()));
+        }
+    });
+}
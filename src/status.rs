@@ -0,0 +1,574 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de;
+use serde::{Deserialize, Deserializer};
+
+use crate::command;
+use crate::config::Color;
+use crate::filter;
+use crate::format::LogFormats;
+use crate::keys::{Action, Keys};
+use crate::output::{Line, LogFile};
+use crate::style;
+
+lazy_static! {
+    static ref START_REGEX: Regex = Regex::new(r"^Starting [Mm]inecraft server").unwrap();
+    static ref DONE_REGEX: Regex = Regex::new(r"^Done \((\d+(?:\.\d+)?s)\)!").unwrap();
+    static ref STOP_REGEX: Regex = Regex::new(r"^Stopping (?:the )?server").unwrap();
+    static ref JOIN_REGEX: Regex = Regex::new(r"^(\S+) joined the game$").unwrap();
+    static ref LEAVE_REGEX: Regex =
+        Regex::new(r"^(\S+) (?:left the game$|lost connection: )").unwrap();
+    /// Written before a player joins:
+    /// Steve[/127.0.0.1:54321] logged in with entity id 123 at ([world]1.5, 64.0, -3.2)
+    static ref LOGIN_REGEX: Regex = Regex::new(
+        r"^(\S+?)\[/(.*?)(?::\d+)?] logged in with entity id \d+ at \((?:\[(.*)])?(.*)\)$"
+    )
+    .unwrap();
+    static ref LAG_REGEX: Regex = Regex::new(r"^Can't keep up!").unwrap();
+    /// Output of the `list` command.
+    static ref LIST_REGEX: Regex =
+        Regex::new(r"^There are (\d+) of a max(?:imum)? of (\d+) players online:?(.*)$").unwrap();
+    static ref CLOCK_REGEX: Regex = Regex::new(r"\d\d:\d\d:\d\d").unwrap();
+}
+
+/// Whether the server is running, as far as the logs tell.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum RunState {
+    /// No log about starting or stopping the server has been seen yet.
+    Unknown,
+    Starting,
+    Running,
+    Stopping,
+}
+
+/// A player that is online.
+pub struct Player {
+    /// When the player joined.
+    pub joined: Instant,
+    /// Time of day when the player joined, in seconds, from the time of the log.
+    clock: Option<u32>,
+    /// IP address that the player connected from.
+    pub ip: Option<String>,
+    /// World and position where the player was when they logged in.
+    pub position: Option<String>,
+}
+
+/// State of the server, derived from the new logs that it writes, and optionally from the logs
+/// that it wrote to `latest.log` before better-console started.
+pub struct ServerState {
+    state: RunState,
+    /// The players online by name, or `None` if they are not known, because the server was
+    /// already running when better-console started.
+    players: Option<BTreeMap<String, Player>>,
+    max_players: Option<usize>,
+    /// How long the server took to start, like `12.3s`.
+    startup_time: Option<String>,
+    /// Number of "Can't keep up!" warnings since the server started.
+    lag_count: usize,
+    /// Number of error logs since midnight.
+    errors_today: usize,
+    /// Time of the last log, to find when a new day starts.
+    last_clock: String,
+}
+
+impl ServerState {
+    pub fn new() -> ServerState {
+        ServerState {
+            state: RunState::Unknown,
+            players: None,
+            max_players: read_max_players(),
+            startup_time: None,
+            lag_count: 0,
+            errors_today: 0,
+            last_clock: String::new(),
+        }
+    }
+
+    /// Build the state from the lines of `latest.log`. Players get the session durations given by
+    /// the times of the logs, as if the last line was written just now.
+    pub fn seed(&mut self, lines: impl Iterator<Item = String>, formats: &LogFormats) {
+        // The lines are only read for the state, so which file they are from does not matter.
+        let file = Arc::new(LogFile {
+            path: None,
+            date: None,
+        });
+        for line in lines {
+            self.update(&Line::Log(line, file.clone()), formats);
+        }
+        let now = match clock_seconds(&self.last_clock) {
+            Some(now) => now,
+            None => return,
+        };
+        for player in self.players.iter_mut().flat_map(|p| p.values_mut()) {
+            if let Some(clock) = player.clock {
+                let ago = (now + 86400 - clock) % 86400;
+                let joined = Instant::now().checked_sub(Duration::from_secs(ago.into()));
+                player.joined = joined.unwrap_or(player.joined);
+            }
+        }
+    }
+
+    /// Get the players online, or `None` if they are not known.
+    pub fn players(&self) -> Option<&BTreeMap<String, Player>> {
+        self.players.as_ref()
+    }
+
+    /// Get the maximum number of players, if known.
+    pub fn max_players(&self) -> Option<usize> {
+        self.max_players
+    }
+
+    /// Update the state from a new line. Returns whether the state changed.
+    pub fn update(&mut self, line: &Line, formats: &LogFormats) -> bool {
+        let (level, time, message) = match line {
+            Line::Log(s, _) => match formats.parse(s) {
+                Some(log) => (log.level, log.time, log.message),
+                None => ("", "", s.as_str()),
+            },
+            // `list` responses come back through RCON.
+            Line::Response(s) => ("", "", s.as_str()),
+            _ => return false,
+        };
+        let message = strip_codes(message);
+        let mut changed = false;
+
+        let clock = CLOCK_REGEX.find(time).map(|m| m.as_str());
+        if let Some(clock) = clock {
+            // Times only go backwards when a new day starts.
+            if clock < self.last_clock.as_str() && self.errors_today != 0 {
+                self.errors_today = 0;
+                changed = true;
+            }
+            self.last_clock = clock.to_string();
+        }
+        if filter::has_level(&["ERROR", "SEVERE", "FATAL"], level) {
+            self.errors_today += 1;
+            changed = true;
+        }
+
+        if START_REGEX.is_match(&message) {
+            self.state = RunState::Starting;
+            self.players = Some(BTreeMap::new());
+            self.max_players = read_max_players().or(self.max_players);
+            self.startup_time = None;
+            self.lag_count = 0;
+        } else if let Some(cap) = DONE_REGEX.captures(&message) {
+            self.state = RunState::Running;
+            self.startup_time = Some(cap[1].to_string());
+        } else if STOP_REGEX.is_match(&message) {
+            self.state = RunState::Stopping;
+            self.players = Some(BTreeMap::new());
+        } else if let Some(cap) = LOGIN_REGEX.captures(&message) {
+            let players = match &mut self.players {
+                Some(players) => players,
+                None => return changed,
+            };
+            let position = match (cap.get(3), &cap[4]) {
+                (Some(world), position) => format!("{} {}", world.as_str(), position),
+                (None, position) => position.to_string(),
+            };
+            players.insert(
+                cap[1].to_string(),
+                Player {
+                    joined: Instant::now(),
+                    clock: clock.and_then(clock_seconds),
+                    ip: Some(cap[2].to_string()),
+                    position: Some(position),
+                },
+            );
+        } else if let Some(cap) = JOIN_REGEX.captures(&message) {
+            let players = match &mut self.players {
+                Some(players) => players,
+                None => return changed,
+            };
+            // The player usually logged in just before this.
+            if !players.contains_key(&cap[1]) {
+                let player = Player {
+                    joined: Instant::now(),
+                    clock: clock.and_then(clock_seconds),
+                    ip: None,
+                    position: None,
+                };
+                players.insert(cap[1].to_string(), player);
+            }
+        } else if let Some(cap) = LEAVE_REGEX.captures(&message) {
+            match &mut self.players {
+                Some(players) => players.remove(&cap[1]),
+                None => return changed,
+            };
+        } else if LAG_REGEX.is_match(&message) {
+            self.lag_count += 1;
+        } else if let Some(cap) = LIST_REGEX.captures(&message) {
+            let names = cap[3].split(',').map(str::trim).filter(|n| !n.is_empty());
+            let mut players = self.players.take().unwrap_or_default();
+            // Keep what is known about the players that are still online.
+            let players = names
+                .map(|name| {
+                    let player = players.remove(name).unwrap_or(Player {
+                        joined: Instant::now(),
+                        clock: clock.and_then(clock_seconds),
+                        ip: None,
+                        position: None,
+                    });
+                    (name.to_string(), player)
+                })
+                .collect::<BTreeMap<_, _>>();
+            self.max_players = cap[2].parse().ok();
+            // The names are left out if the list is too long.
+            if cap[1] == players.len().to_string() {
+                self.players = Some(players);
+            }
+        } else {
+            return changed;
+        }
+        true
+    }
+}
+
+/// Read the maximum number of players from `server.properties`.
+fn read_max_players() -> Option<usize> {
+    command::server_property("max-players")
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+}
+
+/// Convert a time like `14:02:11` to seconds since midnight.
+fn clock_seconds(clock: &str) -> Option<u32> {
+    let mut parts = clock.split(':').map(|p| p.parse::<u32>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600 + m * 60 + s)
+}
+
+/// Remove the formatting codes from `text`.
+fn strip_codes(text: &str) -> String {
+    style::parse(text, Color::default(), false)
+        .into_iter()
+        .map(|(span, _)| span)
+        .collect()
+}
+
+/// Something that can be shown in the status bar.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Field {
+    /// Whether the server is starting, running or stopping.
+    State,
+    /// Number of players online.
+    Players,
+    MaxPlayers,
+    /// Names of the players online.
+    PlayerList,
+    /// How long the server took to start.
+    StartupTime,
+    /// Number of "Can't keep up!" warnings since the server started.
+    LagCount,
+    /// Number of error logs since midnight.
+    ErrorsToday,
+    /// Whether the view is following new logs, or how far it is scrolled back.
+    ScrollPos,
+    /// The keys of the main actions.
+    Keys,
+}
+
+/// Names of the fields in templates.
+const FIELDS: &[(&str, Field)] = &[
+    ("state", Field::State),
+    ("players", Field::Players),
+    ("max_players", Field::MaxPlayers),
+    ("player_list", Field::PlayerList),
+    ("startup_time", Field::StartupTime),
+    ("lag_count", Field::LagCount),
+    ("errors_today", Field::ErrorsToday),
+    ("scroll_pos", Field::ScrollPos),
+    ("keys", Field::Keys),
+];
+
+/// The actions whose keys are shown for `{keys}`.
+const KEY_HINTS: &[(&[Action], &str)] = &[
+    (&[Action::Quit], "quit"),
+    (
+        &[
+            Action::ScrollPageUp,
+            Action::ScrollPageDown,
+            Action::ScrollEnd,
+        ],
+        "scroll",
+    ),
+    (&[Action::ToggleFocus], "arrows scroll"),
+    (&[Action::HistorySearch], "history"),
+    (&[Action::Search], "search"),
+    (&[Action::Filter], "filter"),
+];
+
+/// Shown for values that are not known.
+const UNKNOWN: &str = "?";
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// The text of the status bar, with placeholders such as `{players}` that are replaced by the
+/// state of the server. `{{` and `}}` are literal braces.
+#[derive(Clone, Debug)]
+pub struct StatusTemplate {
+    parts: Vec<Part>,
+}
+
+impl StatusTemplate {
+    pub fn parse(template: &str) -> Result<StatusTemplate, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = match rest.find('}') {
+                        Some(end) => end,
+                        None => return Err(format!("unclosed placeholder in '{}'", template)),
+                    };
+                    let name = &rest[..end];
+                    let field = match FIELDS.iter().find(|(n, _)| *n == name) {
+                        Some(&(_, field)) => field,
+                        None => return Err(format!("unknown placeholder: '{{{}}}'", name)),
+                    };
+                    if !text.is_empty() {
+                        parts.push(Part::Text(mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}'", template)),
+                _ => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(StatusTemplate { parts })
+    }
+
+    /// Fill in the template. `scrolled` is the number of rows that the view is scrolled back from
+    /// the newest logs.
+    pub fn render(&self, server: &ServerState, keys: &Keys, scrolled: isize) -> String {
+        let mut status = String::new();
+        for part in &self.parts {
+            let field = match part {
+                Part::Text(text) => {
+                    status.push_str(text);
+                    continue;
+                }
+                Part::Field(field) => field,
+            };
+            let value = match field {
+                Field::State => match server.state {
+                    RunState::Unknown => UNKNOWN.to_string(),
+                    RunState::Starting => "starting".to_string(),
+                    RunState::Running => "running".to_string(),
+                    RunState::Stopping => "stopping".to_string(),
+                },
+                Field::Players => match &server.players {
+                    Some(players) => players.len().to_string(),
+                    None => UNKNOWN.to_string(),
+                },
+                Field::MaxPlayers => match server.max_players {
+                    Some(max) => max.to_string(),
+                    None => UNKNOWN.to_string(),
+                },
+                Field::PlayerList => match &server.players {
+                    Some(players) => players.keys().cloned().collect::<Vec<_>>().join(", "),
+                    None => UNKNOWN.to_string(),
+                },
+                Field::StartupTime => match &server.startup_time {
+                    Some(time) => time.clone(),
+                    None => UNKNOWN.to_string(),
+                },
+                Field::LagCount => server.lag_count.to_string(),
+                Field::ErrorsToday => server.errors_today.to_string(),
+                Field::ScrollPos if scrolled <= 0 => "following".to_string(),
+                Field::ScrollPos => format!("back {}", scrolled),
+                Field::Keys => keys.hint(KEY_HINTS),
+            };
+            status.push_str(&value);
+        }
+        status
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusTemplate {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let template = String::deserialize(de)?;
+        StatusTemplate::parse(&template).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str =
+        "{state} {players}/{max_players} [{player_list}] {startup_time} {lag_count} {errors_today}";
+
+    fn render(template: &str, server: &ServerState) -> String {
+        StatusTemplate::parse(template)
+            .unwrap()
+            .render(server, &Keys::default(), 0)
+    }
+
+    /// Update the state from logs, where each one is given as its time, level and message.
+    fn feed(server: &mut ServerState, logs: &[(&str, &str, &str)]) {
+        let file = LogFile::today(None);
+        for (time, level, message) in logs {
+            let log = format!("[{}] [Server thread/{}]: {}", time, level, message);
+            server.update(&Line::Log(log, file.clone()), &LogFormats::default());
+        }
+    }
+
+    #[test]
+    fn templates() {
+        let server = ServerState::new();
+        assert_eq!(render("", &server), "");
+        assert_eq!(render("{{state}} = {state}}}", &server), "{state} = ?}");
+        let template = StatusTemplate::parse("{scroll_pos}!").unwrap();
+        assert_eq!(template.render(&server, &Keys::default(), 0), "following!");
+        assert_eq!(template.render(&server, &Keys::default(), 12), "back 12!");
+    }
+
+    #[test]
+    fn keys() {
+        let server = ServerState::new();
+        let keys = "ctrl-Q = quit; PgUp/PgDn/End = scroll; ctrl-O = arrows scroll; \
+                    ctrl-R = history; ctrl-F = search; ctrl-L = filter";
+        assert_eq!(render("{keys}", &server), keys);
+        let keys: Keys = toml::from_str("\"ctrl-x\" = \"quit\"\n\"ctrl-q\" = \"none\"").unwrap();
+        let template = StatusTemplate::parse("{keys}").unwrap();
+        assert!(template
+            .render(&server, &keys, 0)
+            .starts_with("ctrl-X = quit; PgUp"));
+    }
+
+    #[test]
+    fn invalid_templates() {
+        let error = |template| StatusTemplate::parse(template).unwrap_err();
+        assert_eq!(
+            error("{players} {online}"),
+            "unknown placeholder: '{online}'"
+        );
+        assert_eq!(error("{players"), "unclosed placeholder in '{players'");
+        assert_eq!(error("players}"), "unmatched '}' in 'players}'");
+    }
+
+    #[test]
+    fn unknown() {
+        let server = ServerState::new();
+        assert_eq!(render(TEMPLATE, &server), "? ?/? [?] ? 0 0");
+    }
+
+    #[test]
+    fn players() {
+        let mut server = ServerState::new();
+        feed(
+            &mut server,
+            &[
+                ("10:00:00", "INFO", "Starting minecraft server version 1.20.4"),
+                ("10:00:05", "INFO", "Done (5.123s)! For help, type \"help\""),
+                (
+                    "10:01:00",
+                    "INFO",
+                    "Steve[/127.0.0.1:54321] logged in with entity id 1 at ([world]1.5, 64.0, -3.2)",
+                ),
+                ("10:01:00", "INFO", "Steve joined the game"),
+                ("10:02:00", "INFO", "§eAlex joined the game"),
+                ("10:03:00", "INFO", "Herobrine joined the game"),
+                ("10:04:00", "INFO", "Alex lost connection: Disconnected"),
+                ("10:05:00", "INFO", "Herobrine left the game"),
+            ],
+        );
+        assert_eq!(render(TEMPLATE, &server), "running 1/? [Steve] 5.123s 0 0");
+        let steve = &server.players().unwrap()["Steve"];
+        assert_eq!(steve.ip.as_deref(), Some("127.0.0.1"));
+        assert_eq!(steve.position.as_deref(), Some("world 1.5, 64.0, -3.2"));
+
+        feed(&mut server, &[("11:00:00", "INFO", "Stopping the server")]);
+        assert_eq!(render(TEMPLATE, &server), "stopping 0/? [] 5.123s 0 0");
+    }
+
+    #[test]
+    fn list() {
+        let mut server = ServerState::new();
+        // The players are not known until the server starts or lists them.
+        feed(
+            &mut server,
+            &[("10:00:00", "INFO", "Steve joined the game")],
+        );
+        assert_eq!(render("{players}/{max_players}", &server), "?/?");
+        let list = "There are 2 of a max of 20 players online: Steve, Alex";
+        assert!(server.update(&Line::Response(list.to_string()), &LogFormats::default()));
+        assert_eq!(
+            render("{players}/{max_players} {player_list}", &server),
+            "2/20 Alex, Steve"
+        );
+        // The names are left out of long lists.
+        let list = "There are 30 of a max of 50 players online:";
+        server.update(&Line::Response(list.to_string()), &LogFormats::default());
+        assert_eq!(render("{players}/{max_players}", &server), "?/50");
+    }
+
+    #[test]
+    fn lag() {
+        let mut server = ServerState::new();
+        let lag = "Can't keep up! Is the server overloaded? Running 2500ms or 50 ticks behind";
+        feed(
+            &mut server,
+            &[("10:00:00", "WARN", lag), ("10:01:00", "WARN", lag)],
+        );
+        assert_eq!(render("{lag_count}", &server), "2");
+        // The count starts again with the server.
+        feed(
+            &mut server,
+            &[
+                (
+                    "11:00:00",
+                    "INFO",
+                    "Starting minecraft server version 1.20.4",
+                ),
+                ("11:01:00", "WARN", lag),
+            ],
+        );
+        assert_eq!(render("{lag_count}", &server), "1");
+    }
+
+    #[test]
+    fn errors_today() {
+        let mut server = ServerState::new();
+        feed(
+            &mut server,
+            &[
+                ("23:58:00", "ERROR", "Something went wrong"),
+                ("23:59:00", "INFO", "Something else"),
+                ("23:59:30", "ERROR", "Something went wrong again"),
+            ],
+        );
+        assert_eq!(render("{errors_today}", &server), "2");
+        // A new day starts.
+        feed(&mut server, &[("00:00:01", "INFO", "Good morning")]);
+        assert_eq!(render("{errors_today}", &server), "0");
+        feed(&mut server, &[("00:00:02", "ERROR", "Bad morning")]);
+        assert_eq!(render("{errors_today}", &server), "1");
+    }
+}
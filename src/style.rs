@@ -0,0 +1,253 @@
+use rustbox::{Color as RbColor, RB_BOLD, RB_REVERSE, RB_UNDERLINE};
+
+use crate::config::Color;
+
+/// Colors of the Minecraft color codes `§0` to `§f` in the 256-color palette.
+const MINECRAFT_COLORS: [u16; 16] = [
+    16, 19, 34, 37, 124, 127, 214, 248, 240, 63, 83, 87, 203, 207, 227, 231,
+];
+
+/// Split `text` into spans styled by the Minecraft formatting codes (such as `§a` and `§l`) and
+/// ANSI SGR escape sequences that it contains, starting with the color `base`. The codes are
+/// removed from the text. If `render` is false, all spans have the color `base`.
+pub fn parse(text: &str, base: Color, render: bool) -> Vec<(&str, Color)> {
+    let mut spans = Vec::new();
+    let mut color = base;
+    // Byte index of the start of the current span
+    let mut start = 0;
+    let mut iter = text.char_indices().peekable();
+    while let Some((i, ch)) = iter.next() {
+        if ch != '§' && ch != '\x1b' {
+            continue;
+        }
+        if start < i {
+            spans.push((&text[start..i], color));
+        }
+        let mut code = String::new();
+        if ch == '§' {
+            if let Some((j, c)) = iter.next() {
+                code.push(c);
+                // Hex colors are written as §x§r§r§g§g§b§b.
+                let hex = text[j + c.len_utf8()..]
+                    .chars()
+                    .take(12)
+                    .collect::<Vec<_>>();
+                let is_hex = hex.len() == 12
+                    && hex
+                        .chunks(2)
+                        .all(|p| p[0] == '§' && p[1].is_ascii_hexdigit());
+                if (c == 'x' || c == 'X') && is_hex {
+                    code.extend(hex.iter().skip(1).step_by(2));
+                    for _ in 0..12 {
+                        iter.next();
+                    }
+                }
+            }
+            minecraft_code(&code, base, &mut color);
+        } else if let Some(&(_, '[')) = iter.peek() {
+            // Control sequence: ESC [ parameters final-byte
+            iter.next();
+            let mut last = '\0';
+            for (_, c) in iter.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    last = c;
+                    break;
+                }
+                code.push(c);
+            }
+            // Only SGR sequences change the style, and the others are removed.
+            if last == 'm' {
+                sgr(&code, base, &mut color);
+            }
+        }
+        if !render {
+            color = base;
+        }
+        start = match iter.peek() {
+            Some(&(j, _)) => j,
+            None => text.len(),
+        };
+    }
+    if start < text.len() {
+        spans.push((&text[start..], color));
+    }
+    spans
+}
+
+/// Apply the Minecraft formatting code `code` (without the `§`) to `color`.
+fn minecraft_code(code: &str, base: Color, color: &mut Color) {
+    match code.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some(c @ ('0'..='9' | 'a'..='f')) => {
+            // Colors reset the formatting.
+            let index = c.to_digit(16).unwrap() as usize;
+            *color = base;
+            color.fg = RbColor::Byte(MINECRAFT_COLORS[index]);
+        }
+        Some('x') if code.len() == 7 => {
+            let rgb = u32::from_str_radix(&code[1..], 16).unwrap();
+            *color = base;
+            color.fg = RbColor::Byte(rgb_color(rgb >> 16, rgb >> 8 & 0xff, rgb & 0xff));
+        }
+        Some('l') => color.sty.insert(RB_BOLD),
+        Some('n') => color.sty.insert(RB_UNDERLINE),
+        Some('r') => *color = base,
+        // Italic, strikethrough and obfuscated text cannot be shown, and unknown codes are
+        // ignored.
+        _ => (),
+    }
+}
+
+/// Apply the parameters of an SGR escape sequence to `color`.
+fn sgr(params: &str, base: Color, color: &mut Color) {
+    let mut params = params.split(';').map(|p| p.parse::<u32>().unwrap_or(0));
+    while let Some(param) = params.next() {
+        match param {
+            0 => *color = base,
+            1 => color.sty.insert(RB_BOLD),
+            4 => color.sty.insert(RB_UNDERLINE),
+            7 => color.sty.insert(RB_REVERSE),
+            22 => color.sty.remove(RB_BOLD),
+            24 => color.sty.remove(RB_UNDERLINE),
+            27 => color.sty.remove(RB_REVERSE),
+            30..=37 => color.fg = RbColor::Byte(palette(param - 30)),
+            40..=47 => color.bg = RbColor::Byte(palette(param - 40)),
+            90..=97 => color.fg = RbColor::Byte(palette(param - 90 + 8)),
+            100..=107 => color.bg = RbColor::Byte(palette(param - 100 + 8)),
+            38 | 48 => {
+                let value = match params.next() {
+                    Some(5) => params.next().map(palette),
+                    Some(2) => match (params.next(), params.next(), params.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(rgb_color(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    if param == 38 {
+                        color.fg = RbColor::Byte(value);
+                    } else {
+                        color.bg = RbColor::Byte(value);
+                    }
+                }
+            }
+            39 => color.fg = base.fg,
+            49 => color.bg = base.bg,
+            _ => (),
+        }
+    }
+}
+
+/// Convert an index in the 256-color palette to a color. Color 0 means the default color, so
+/// black is shown with the black of the color cube.
+fn palette(index: u32) -> u16 {
+    match index {
+        0 => 16,
+        1..=255 => index as u16,
+        _ => 0,
+    }
+}
+
+/// Find the closest color to an RGB color in the color cube of the 256-color palette.
+fn rgb_color(r: u32, g: u32, b: u32) -> u16 {
+    let level = |v: u32| match v.min(255) {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    };
+    (16 + 36 * level(r) + 6 * level(g) + level(b)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use rustbox::{Style, RB_NORMAL};
+
+    use super::*;
+
+    fn base() -> Color {
+        Color::new(0, 0, RB_NORMAL)
+    }
+
+    /// Parse with the base color, and get the text, foreground color and style of each span.
+    fn spans(text: &str) -> Vec<(&str, RbColor, Style)> {
+        parse(text, base(), true)
+            .into_iter()
+            .map(|(s, c)| (s, c.fg, c.sty))
+            .collect()
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(spans("hello"), vec![("hello", RbColor::Byte(0), RB_NORMAL)]);
+        assert!(spans("").is_empty());
+    }
+
+    #[test]
+    fn minecraft_codes() {
+        assert_eq!(
+            spans("§aGreen §lbold§r plain"),
+            vec![
+                ("Green ", RbColor::Byte(83), RB_NORMAL),
+                ("bold", RbColor::Byte(83), RB_BOLD),
+                (" plain", RbColor::Byte(0), RB_NORMAL),
+            ]
+        );
+        // Colors reset the formatting.
+        assert_eq!(
+            spans("§l§cred"),
+            vec![("red", RbColor::Byte(203), RB_NORMAL)]
+        );
+        // Unknown codes and a § at the end are removed.
+        assert_eq!(spans("§kab§"), vec![("ab", RbColor::Byte(0), RB_NORMAL)]);
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(
+            spans("§x§f§f§0§0§0§0red"),
+            vec![("red", RbColor::Byte(196), RB_NORMAL)]
+        );
+        // An incomplete hex color is an unknown code, followed by the other codes.
+        assert_eq!(
+            spans("§x§fnot"),
+            vec![("not", RbColor::Byte(231), RB_NORMAL)]
+        );
+    }
+
+    #[test]
+    fn ansi_sequences() {
+        assert_eq!(
+            spans("\x1b[1;31mred\x1b[22m thin\x1b[0m plain"),
+            vec![
+                ("red", RbColor::Byte(1), RB_BOLD),
+                (" thin", RbColor::Byte(1), RB_NORMAL),
+                (" plain", RbColor::Byte(0), RB_NORMAL),
+            ]
+        );
+        assert_eq!(
+            spans("\x1b[38;5;208mo\x1b[38;2;0;0;255mb\x1b[30mk"),
+            vec![
+                ("o", RbColor::Byte(208), RB_NORMAL),
+                ("b", RbColor::Byte(21), RB_NORMAL),
+                ("k", RbColor::Byte(16), RB_NORMAL),
+            ]
+        );
+        // Other control sequences are removed.
+        assert_eq!(
+            spans("a\x1b[2Kb"),
+            vec![
+                ("a", RbColor::Byte(0), RB_NORMAL),
+                ("b", RbColor::Byte(0), RB_NORMAL)
+            ]
+        );
+    }
+
+    #[test]
+    fn not_rendered() {
+        let spans = parse("§cred\x1b[1m bold", base(), false);
+        let texts: Vec<_> = spans.iter().map(|(s, _)| *s).collect();
+        assert_eq!(texts, vec!["red", " bold"]);
+        assert!(spans
+            .iter()
+            .all(|(_, c)| c.fg == RbColor::Byte(0) && c.sty == RB_NORMAL));
+    }
+}